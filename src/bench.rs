@@ -0,0 +1,110 @@
+use reindeer::camera::Camera;
+use reindeer::drawer::WinSize;
+use reindeer::renderer::{
+    AoQuality,
+    Rasterizer,
+    Renderer
+};
+use reindeer::scene::Model;
+use reindeer::transform;
+use reindeer::vector::{
+    Vec2,
+    Vec3,
+    Vec4
+};
+
+use std::hint::black_box;
+use std::time::{
+    Duration,
+    Instant
+};
+
+const FRAMES: usize = 100;
+const ITERATIONS: u32 = 1_000_000;
+
+// Timings are only meaningful for binaries built with --release
+pub fn run(model: &Model) {
+    frames("frame with AO and shadows", model, AoQuality::Full, true, Rasterizer::FixedPoint);
+    frames("frame with half resolution AO and shadows", model, AoQuality::Half, true, Rasterizer::FixedPoint);
+    frames("frame without AO and shadows", model, AoQuality::Off, false, Rasterizer::FixedPoint);
+    frames("frame without AO and shadows, float rasterizer", model, AoQuality::Off, false, Rasterizer::Float);
+    matrix_multiplication();
+    barycentric_coordinates();
+}
+
+// Renders the model orbited by the camera like in the demo, without a terminal
+fn frames(name: &str, model: &Model, ao_quality: AoQuality, shadows: bool, rasterizer: Rasterizer) {
+    let mut renderer = Renderer::with_size(WinSize { cols: 160, rows: 60 });
+    renderer.set_rasterizer(rasterizer);
+    renderer.light(&Vec3 { x: 2.0, y: 5.0, z: 1.0 }.normalized());
+    renderer.set_ao_quality(ao_quality);
+    renderer.set_shadows(shadows);
+
+    let mut frame_times = Vec::with_capacity(FRAMES);
+
+    for frame in 0..FRAMES {
+        let start = Instant::now();
+
+        let yaw = 0.03 * frame as f32;
+        let pitch = 0.6 * (0.05 * frame as f32).sin();
+        renderer.set_camera(&Camera::orbit(model.position, 1.5, yaw, pitch));
+
+        renderer.refresh();
+        renderer.model(&model.mesh, &model.material, &model.position);
+
+        frame_times.push(start.elapsed());
+    }
+
+    let total: Duration = frame_times.iter().sum();
+    let slowest = frame_times.iter().max().unwrap();
+
+    println!(
+        "{}: {:.2} ms per frame, slowest {:.2} ms ({} frames)",
+        name, milliseconds(total) / FRAMES as f64, milliseconds(*slowest), FRAMES
+    );
+}
+
+fn matrix_multiplication() {
+    let a = transform::look_at(
+        &Vec3 { x: 1.0, y: 2.0, z: 3.0 }, &Vec3::ZERO, &Vec3 { x: 0.0, y: 1.0, z: 0.0 }
+    );
+    let mut b = transform::perspective(3.0);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        b = black_box(a) * black_box(b);
+    }
+    black_box(b);
+
+    print_iteration_time("Matrix4 multiplication", start.elapsed());
+
+    let v = Vec4 { x: 1.0, y: 2.0, z: 3.0, w: 1.0 };
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(black_box(a) * black_box(v));
+    }
+
+    print_iteration_time("Matrix4 vector multiplication", start.elapsed());
+}
+
+fn barycentric_coordinates() {
+    let a = Vec2 { x: 0.0, y: 0.0 };
+    let b = Vec2 { x: 1.0, y: 0.0 };
+    let c = Vec2 { x: 0.0, y: 1.0 };
+    let p = Vec2 { x: 0.25, y: 0.25 };
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(transform::to_barycentric(black_box(&a), &b, &c, black_box(&p)));
+    }
+
+    print_iteration_time("to_barycentric", start.elapsed());
+}
+
+fn print_iteration_time(name: &str, elapsed: Duration) {
+    println!("{}: {:.2} ns per call", name, elapsed.as_nanos() as f64 / f64::from(ITERATIONS));
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,49 @@
+use crate::transform;
+use crate::matrix::Matrix4;
+use crate::vector::Vec3;
+
+#[derive(Clone, Copy)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3
+}
+
+impl Camera {
+    pub fn new(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        Camera { eye, target, up }
+    }
+
+    // Yaw rotates around the Y axis starting from +Z, pitch raises the eye above the XZ plane
+    pub fn orbit(target: Vec3, radius: f32, yaw: f32, pitch: f32) -> Self {
+        let offset = Vec3 {
+            x: yaw.sin() * pitch.cos(),
+            y: pitch.sin(),
+            z: yaw.cos() * pitch.cos()
+        };
+
+        Camera {
+            eye: target + radius * offset,
+            target,
+            up: Vec3 { x: 0.0, y: 1.0, z: 0.0 }
+        }
+    }
+
+    pub fn view_matrix(&self) -> Matrix4 {
+        transform::look_at(&self.eye, &self.target, &self.up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orbit_without_rotation_looks_along_z() {
+        let target = Vec3 { x: 1.0, y: 2.0, z: 3.0 };
+        let camera = Camera::orbit(target, 5.0, 0.0, 0.0);
+
+        assert!(camera.eye.approx_eq(&Vec3 { x: 1.0, y: 2.0, z: 8.0 }, 1e-6));
+        assert_eq!(camera.target, target);
+    }
+}
@@ -1,8 +1,11 @@
 use crate::error::Error;
+use crate::image::Image;
 use crate::primitive::{
     Color,
     Size
 };
+use crate::terminal;
+use crate::text;
 
 #[derive(Clone)]
 pub struct WinSize {
@@ -10,56 +13,379 @@ pub struct WinSize {
     pub rows: i32
 }
 
+#[derive(Clone, Copy)]
+pub enum Background {
+    Solid(Color),
+    // Blended from the top row to the bottom row
+    Gradient {
+        top: Color,
+        bottom: Color
+    }
+}
+
+impl Background {
+    fn at_row(&self, y: i32, height: i32) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { top, bottom } => {
+                let t = if height > 1 { y as f32 / (height - 1) as f32 } else { 0.0 };
+                top.mix(bottom, t)
+            }
+        }
+    }
+}
+
+// Target of the 2D primitives
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layer {
+    // Drawn into the image, which is cleared together with the scene
+    Scene,
+    // Drawn over the image and kept until clear_overlay, e.g. for a HUD updated less often than the scene
+    Overlay
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorMode {
+    // 24-bit colors
+    TrueColor,
+    // Colors quantized to the color cube and the gray ramp of the 256 color palette
+    Ansi256
+}
+
+impl ColorMode {
+    // Length of "\x1b[38;2;000;000;000m" or "\x1b[38;5;000m"
+    fn sequence_len(&self) -> usize {
+        match self {
+            ColorMode::TrueColor => 19,
+            ColorMode::Ansi256 => 11
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GlyphMode {
+    // Lower half block on a background, two pixels per character
+    HalfBlock,
+    // Full block, one pixel per character
+    FullBlock,
+    // Any character drawn with the pixel color, one pixel per character
+    Custom(char),
+    // Uncolored characters chosen by the pixel luminance, one pixel per character
+    Ascii
+}
+
+impl GlyphMode {
+    fn pixels_per_cell(&self) -> usize {
+        match self {
+            GlyphMode::HalfBlock => 2,
+            GlyphMode::FullBlock | GlyphMode::Custom(_) | GlyphMode::Ascii => 1
+        }
+    }
+
+    // Color sequences for the pixels of a character, from top to bottom
+    fn sequence(&self, color_mode: ColorMode) -> &'static [u8] {
+        match (self, color_mode) {
+            (GlyphMode::HalfBlock, ColorMode::TrueColor) => Drawer::DRAWING_SEQUENCE,
+            (GlyphMode::HalfBlock, ColorMode::Ansi256) => Drawer::DRAWING_SEQUENCE_256,
+            (GlyphMode::FullBlock, ColorMode::TrueColor) | (GlyphMode::Custom(_), ColorMode::TrueColor) =>
+                Drawer::FOREGROUND_SEQUENCE,
+            (GlyphMode::FullBlock, ColorMode::Ansi256) | (GlyphMode::Custom(_), ColorMode::Ansi256) =>
+                Drawer::FOREGROUND_SEQUENCE_256,
+            (GlyphMode::Ascii, _) => b""
+        }
+    }
+
+    fn glyph(&self) -> Vec<u8> {
+        let glyph = match self {
+            GlyphMode::HalfBlock => '\u{2584}',
+            GlyphMode::FullBlock => '\u{2588}',
+            GlyphMode::Custom(glyph) => *glyph,
+            GlyphMode::Ascii => ' '
+        };
+
+        let mut buf = [0u8; 4];
+        glyph.encode_utf8(&mut buf).as_bytes().to_vec()
+    }
+}
+
 pub struct Drawer {
-    stdout: std::io::Stdout,
+    writer: Box<dyn std::io::Write>,
     win_size: WinSize,
     win_buf: Vec<u8>,
     plane_size: Size,
-    img_buf: Vec<Color>
+    img_buf: Vec<Color>,
+    // Last displayed frame with the layers composited, empty if none was displayed at this size
+    displayed_buf: Vec<Color>,
+    glyph_mode: GlyphMode,
+    color_mode: ColorMode,
+    // Fraction of every pixel covered by geometry when antialiasing,
+    // partially covered pixels are blended with the background when displayed
+    coverage: Option<Vec<f32>>,
+    // Color and pixels of an outline drawn over the frame, until it is cleared
+    outline: Option<(Color, Vec<bool>)>,
+    // Pixels drawn on the overlay layer, none until something is drawn there
+    overlay: Option<Vec<Option<Color>>>,
+    layer: Layer,
+    background: Background,
+    // Colors reduced to the 256 color palette or the ASCII ramp are dithered with a Bayer matrix
+    dithering: bool,
+    // Brightness and contrast applied to the colors written to the terminal
+    display_adjust: Option<(f32, f32)>,
+    // Length of a single character in the window buffer
+    cell_len: usize,
+    cursor_hidden: bool,
+    // Frames are drawn on the alternate screen, so they do not remain in the scrollback
+    alt_screen: bool,
+    // Number of frames written to the output at once, which saves syscalls at high frame rates
+    flush_interval: usize,
+    // Frames displayed since the last flush
+    pending_frames: Vec<u8>,
+    pending_frame_count: usize,
+    // Time spent writing out the last displayed frame, zero if it was not flushed
+    output_time: std::time::Duration
 }
 
 
 impl Drawer {
-    const DRAWING_BLOCK: &'static [u8] = b"\xE2\x96\x84";
     const DRAWING_SEQUENCE: &'static [u8] = b"\x1b[48;2;000;000;000m\x1b[38;2;000;000;000m";
+    const FOREGROUND_SEQUENCE: &'static [u8] = b"\x1b[38;2;000;000;000m";
+    const DRAWING_SEQUENCE_256: &'static [u8] = b"\x1b[48;5;000m\x1b[38;5;000m";
+    const FOREGROUND_SEQUENCE_256: &'static [u8] = b"\x1b[38;5;000m";
+    // Channel values of the 6x6x6 color cube starting at index 16 of the 256 color palette
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    // Thresholds of ordered dithering, from 0 to 15
+    const BAYER_MATRIX: [[u8; 4]; 4] = [
+        [0, 8, 2, 10],
+        [12, 4, 14, 6],
+        [3, 11, 1, 9],
+        [15, 7, 13, 5]
+    ];
+    // Channels are dithered across the distance between the levels of the color cube
+    const DITHER_SPREAD: f32 = 40.0;
+    // Width to height ratio of a typical terminal character
+    const CELL_ASPECT: f32 = 0.5;
+    // Columns and rows used if the size of the terminal is unknown
+    const DEFAULT_TERMINAL_SIZE: (usize, usize) = (80, 24);
+    // Characters of increasing brightness
+    const ASCII_RAMP: &'static [u8] = b" .:-=+*#%@";
+    const HIDE_CURSOR_SEQUENCE: &'static [u8] = b"\x1b[?25l";
+    const MOVE_TO_ORIGIN_SEQUENCE: &'static [u8] = b"\x1B[0;0H";
+    // Reset colors, clear the screen and show the cursor
+    const RESTORE_SEQUENCE: &'static [u8] = b"\x1b[0m\x1b[2J\x1b[H\x1b[?25h";
+    const ENTER_ALT_SCREEN_SEQUENCE: &'static [u8] = b"\x1b[?1049h";
+    const LEAVE_ALT_SCREEN_SEQUENCE: &'static [u8] = b"\x1b[?1049l";
 
     pub fn new() -> Self {
         let (cols, rows) = Self::get_terminal_size();
 
+        Self::with_writer(
+            std::io::stdout(),
+            WinSize { cols: cols as i32, rows: rows as i32 }
+        ).with_alt_screen(true).with_color_mode(terminal::detect_color_support())
+    }
+
+    pub fn with_writer<W: std::io::Write + 'static>(writer: W, win_size: WinSize) -> Self {
+        let glyph_mode = GlyphMode::HalfBlock;
+        let color_mode = ColorMode::TrueColor;
+        let cols = win_size.cols as usize;
+        let rows = win_size.rows as usize * glyph_mode.pixels_per_cell();
+
         Drawer {
-            stdout: std::io::stdout(),
-            win_size: WinSize { cols: cols as i32, rows: rows as i32 },
-            win_buf: Self::create_window_buffer(cols, rows),
-            plane_size: Size { width: cols as i32, height: rows as i32 * 2 },
-            img_buf: Self::create_image_buffer(cols, rows)
+            writer: Box::new(writer),
+            win_size: win_size.clone(),
+            win_buf: Self::create_window_buffer(&win_size, glyph_mode, color_mode),
+            plane_size: Size { width: cols as i32, height: rows as i32 },
+            img_buf: Self::create_image_buffer(cols, rows),
+            displayed_buf: Vec::new(),
+            glyph_mode,
+            color_mode,
+            coverage: None,
+            outline: None,
+            overlay: None,
+            layer: Layer::Scene,
+            background: Background::Solid(Color::BLACK),
+            dithering: false,
+            display_adjust: None,
+            cell_len: glyph_mode.sequence(color_mode).len() + glyph_mode.glyph().len(),
+            cursor_hidden: false,
+            alt_screen: false,
+            flush_interval: 1,
+            pending_frames: Vec::new(),
+            pending_frame_count: 0,
+            output_time: std::time::Duration::ZERO
         }
     }
 
-    fn get_terminal_size() -> (usize, usize) {
-        unsafe {
-            let mut ws: libc::winsize = std::mem::MaybeUninit::uninit().assume_init();
-            libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws);
-            (usize::from(ws.ws_col), usize::from(ws.ws_row))
+    pub fn with_alt_screen(mut self, alt_screen: bool) -> Self {
+        self.alt_screen = alt_screen;
+        self
+    }
+
+    pub fn with_glyph_mode(mut self, glyph_mode: GlyphMode) -> Self {
+        self.glyph_mode = glyph_mode;
+        self.cell_len = glyph_mode.sequence(self.color_mode).len() + glyph_mode.glyph().len();
+        self.resize(self.win_size.clone());
+        self
+    }
+
+    pub fn with_color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self.cell_len = self.glyph_mode.sequence(color_mode).len() + self.glyph_mode.glyph().len();
+        self.resize(self.win_size.clone());
+        self
+    }
+
+    pub fn with_ascii(self) -> Self {
+        self.with_glyph_mode(GlyphMode::Ascii)
+    }
+
+    pub fn resize(&mut self, win_size: WinSize) {
+        let cols = win_size.cols as usize;
+        let rows = win_size.rows as usize * self.glyph_mode.pixels_per_cell();
+
+        self.win_buf = Self::create_window_buffer(&win_size, self.glyph_mode, self.color_mode);
+        self.plane_size = Size { width: cols as i32, height: rows as i32 };
+        self.img_buf = Self::create_image_buffer(cols, rows);
+        self.displayed_buf = Vec::new();
+        self.outline = None;
+        self.overlay = None;
+        if self.coverage.is_some() {
+            self.coverage = Some(vec![0.0; cols * rows]);
+        }
+        self.win_size = win_size;
+    }
+
+    pub fn set_antialiasing(&mut self, antialiasing: bool) {
+        self.coverage = if antialiasing {
+            Some(vec![0.0; self.img_buf.len()])
+        }
+        else {
+            None
+        };
+    }
+
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+    }
+
+    // Brightness of 0 and contrast of 1 leave the colors unchanged,
+    // saved frames are not adjusted
+    pub fn set_display_adjust(&mut self, brightness: f32, contrast: f32) {
+        self.display_adjust = Some((brightness, contrast));
+    }
+
+    // Frames are kept in memory until the given number of them is displayed
+    pub fn set_flush_interval(&mut self, frames: usize) {
+        self.flush_interval = frames.max(1);
+    }
+
+    // Offset from -0.5 to 0.5 added before quantizing the color of a pixel
+    fn dither_offset(&self, x: i32, y: i32) -> f32 {
+        if !self.dithering {
+            return 0.0;
+        }
+
+        let threshold = Self::BAYER_MATRIX[(y % 4) as usize][(x % 4) as usize];
+        (f32::from(threshold) + 0.5) / 16.0 - 0.5
+    }
+
+    pub fn add_coverage(&mut self, x: i32, y: i32, coverage: f32) {
+        let index = (x + y * self.plane_size.width) as usize;
+        if let Some(buffer) = &mut self.coverage {
+            buffer[index] += coverage;
+        }
+    }
+
+    // Color of the pixel after blending partially covered pixels with the background
+    fn resolved_vertex(&self, x: i32, y: i32) -> Color {
+        if let Some(overlay) = &self.overlay {
+            if let Some(color) = overlay[(x + y * self.plane_size.width) as usize] {
+                return color;
+            }
+        }
+
+        if let Some((outline_color, pixels)) = &self.outline {
+            if pixels[(x + y * self.plane_size.width) as usize] {
+                return *outline_color;
+            }
+        }
+
+        let color = *self.vertex_ref(x, y);
+
+        match &self.coverage {
+            Some(buffer) => {
+                let coverage = buffer[(x + y * self.plane_size.width) as usize];
+                if coverage < 1.0 {
+                    self.background.at_row(y, self.plane_size.height).mix(&color, coverage)
+                }
+                else {
+                    color
+                }
+            },
+            None => color
         }
     }
 
-    fn create_window_buffer(cols: usize, rows: usize) -> Vec<u8> {
-        let mut win_buf = Vec::with_capacity(
-            rows * cols * (Self::DRAWING_BLOCK.len() + Self::DRAWING_SEQUENCE.len()) + rows - 1
-        );
+    pub fn win_size(&self) -> WinSize {
+        self.win_size.clone()
+    }
+
+    // Width to height ratio of a single pixel
+    pub fn pixel_aspect(&self) -> f32 {
+        Self::CELL_ASPECT * self.glyph_mode.pixels_per_cell() as f32
+    }
+
+    // Columns and rows of the terminal. Some containers and pipes report a size of 0,
+    // which is replaced with $COLUMNS and $LINES or with the default size
+    pub fn get_terminal_size() -> (usize, usize) {
+        let (cols, rows) = unsafe {
+            let mut ws: libc::winsize = std::mem::zeroed();
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) == -1 {
+                (0, 0)
+            }
+            else {
+                (usize::from(ws.ws_col), usize::from(ws.ws_row))
+            }
+        };
+
+        Self::size_or_fallback(cols, rows, |variable| std::env::var(variable).ok())
+    }
 
-        for _ in 0..rows {
-            for _ in 0..cols {
-                win_buf.extend_from_slice(Self::DRAWING_SEQUENCE);
-                win_buf.extend_from_slice(Self::DRAWING_BLOCK);
+    // Every dimension of 0 is replaced separately, environment variables are read with var
+    fn size_or_fallback(cols: usize, rows: usize, var: impl Fn(&str) -> Option<String>) -> (usize, usize) {
+        let fallback = |size: usize, variable: &str, default: usize| {
+            if size > 0 {
+                return size;
             }
+
+            var(variable)
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .filter(|&value| value > 0)
+                .unwrap_or(default)
+        };
+
+        (
+            fallback(cols, "COLUMNS", Self::DEFAULT_TERMINAL_SIZE.0),
+            fallback(rows, "LINES", Self::DEFAULT_TERMINAL_SIZE.1)
+        )
+    }
+
+    fn create_window_buffer(win_size: &WinSize, glyph_mode: GlyphMode, color_mode: ColorMode) -> Vec<u8> {
+        let cells = (win_size.cols * win_size.rows) as usize;
+        let sequence = glyph_mode.sequence(color_mode);
+        let glyph = glyph_mode.glyph();
+        let mut win_buf = Vec::with_capacity(cells * (sequence.len() + glyph.len()));
+
+        for _ in 0..cells {
+            win_buf.extend_from_slice(sequence);
+            win_buf.extend_from_slice(&glyph);
         }
 
         win_buf
     }
 
     fn create_image_buffer(cols: usize, rows: usize) -> Vec<Color> {
-        let mut img_buf = Vec::with_capacity(rows * cols * 2);
+        let mut img_buf = Vec::with_capacity(rows * cols);
         for _ in 0..img_buf.capacity() {
             img_buf.push(Color::BLACK);
         }
@@ -78,13 +404,73 @@ impl Drawer {
     }
 
     fn set_win_vertex(&mut self, x: i32, y: i32, color: &Color) {
-        let segment = (2 * x + y % 2 + 2 * self.win_size.cols * (y / 2)) as usize;
-        // 7 - length of "\x1b[38;2;"
-        let pos = segment * (Self::DRAWING_SEQUENCE.len() / 2) + Self::DRAWING_BLOCK.len() * (segment / 2) + 7;
-        // set color every 4 characters ("000;")
-        self.set_win_color_value(pos, color.r);
-        self.set_win_color_value(pos + 4, color.g);
-        self.set_win_color_value(pos + 8, color.b);
+        let adjusted;
+        let color = match self.display_adjust {
+            Some((brightness, contrast)) => {
+                adjusted = color.adjusted(brightness, contrast);
+                &adjusted
+            },
+            None => color
+        };
+
+        let pixels_per_cell = self.glyph_mode.pixels_per_cell() as i32;
+        let cell = (x + self.win_size.cols * (y / pixels_per_cell)) as usize;
+
+        if self.glyph_mode == GlyphMode::Ascii {
+            let levels = (Self::ASCII_RAMP.len() - 1) as f32;
+            let ramp_index = (color.luminance() * levels + self.dither_offset(x, y)).round().clamp(0.0, levels);
+            self.win_buf[cell * self.cell_len] = Self::ASCII_RAMP[ramp_index as usize];
+            return;
+        }
+
+        // 7 - length of "\x1b[38;2;" or "\x1b[38;5;"
+        let pos = cell * self.cell_len + (y % pixels_per_cell) as usize * self.color_mode.sequence_len() + 7;
+
+        match self.color_mode {
+            ColorMode::TrueColor => {
+                // set color every 4 characters ("000;")
+                self.set_win_color_value(pos, color.r);
+                self.set_win_color_value(pos + 4, color.g);
+                self.set_win_color_value(pos + 8, color.b);
+            },
+            ColorMode::Ansi256 => {
+                let offset = self.dither_offset(x, y) * Self::DITHER_SPREAD;
+                let dither = |channel: u8| (f32::from(channel) + offset).round().clamp(0.0, 255.0) as u8;
+                let dithered = Color { r: dither(color.r), g: dither(color.g), b: dither(color.b) };
+
+                self.set_win_color_value(pos, Self::ansi256_index(&dithered));
+            }
+        }
+    }
+
+    // The nearest color of the color cube or of the gray ramp
+    fn ansi256_index(color: &Color) -> u8 {
+        let nearest_level = |channel: u8| {
+            (0..Self::CUBE_LEVELS.len())
+                .min_by_key(|&i| (i32::from(Self::CUBE_LEVELS[i]) - i32::from(channel)).abs())
+                .unwrap()
+        };
+
+        let distance = |other: &Color| {
+            let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+            d(color.r, other.r) + d(color.g, other.g) + d(color.b, other.b)
+        };
+
+        let (r, g, b) = (nearest_level(color.r), nearest_level(color.g), nearest_level(color.b));
+        let cube_color = Color { r: Self::CUBE_LEVELS[r], g: Self::CUBE_LEVELS[g], b: Self::CUBE_LEVELS[b] };
+
+        // The gray ramp goes from 8 to 238 in steps of 10
+        let average = (u32::from(color.r) + u32::from(color.g) + u32::from(color.b)) / 3;
+        let gray = ((average.max(8) - 8 + 5) / 10).min(23) as u8;
+        let gray_value = 8 + 10 * gray;
+        let gray_color = Color { r: gray_value, g: gray_value, b: gray_value };
+
+        if distance(&gray_color) < distance(&cube_color) {
+            232 + gray
+        }
+        else {
+            (16 + 36 * r + 6 * g + b) as u8
+        }
     }
 
     #[inline(always)]
@@ -99,30 +485,166 @@ impl Drawer {
 
     #[inline(always)]
     pub fn set_vertex(&mut self, x: i32, y: i32, color: &Color) {
-        *self.vertex_ref_mut(x, y) = color.clone();
+        *self.vertex_ref_mut(x, y) = *color;
+    }
+
+    // Layer on which the following 2D primitives are drawn
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.layer = layer;
+    }
+
+    pub fn clear_overlay(&mut self) {
+        self.overlay = None;
+    }
+
+    // 2D primitives are drawn over the image and clipped to its bounds
+    pub fn point(&mut self, x: i32, y: i32, color: &Color) {
+        if x < 0 || y < 0 || x >= self.plane_size.width || y >= self.plane_size.height {
+            return;
+        }
+
+        match self.layer {
+            Layer::Scene => {
+                self.set_vertex(x, y, color);
+                self.add_coverage(x, y, 1.0);
+            },
+            Layer::Overlay => {
+                let len = self.img_buf.len();
+                let overlay = self.overlay.get_or_insert_with(|| vec![None; len]);
+                overlay[(x + y * self.plane_size.width) as usize] = Some(*color);
+            }
+        }
+    }
+
+    // Bresenham's line algorithm
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: &Color) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+
+        let mut error = dx + dy;
+        let mut x = x0;
+        let mut y = y0;
+
+        loop {
+            self.point(x, y, color);
+
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            if 2 * error >= dy {
+                error += dy;
+                x += step_x;
+            }
+
+            if 2 * error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    // Outline of a rectangle with the top left corner at (x, y)
+    pub fn rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: &Color) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        let right = x + width - 1;
+        let bottom = y + height - 1;
+
+        self.line(x, y, right, y, color);
+        self.line(x, bottom, right, bottom, color);
+        self.line(x, y, x, bottom, color);
+        self.line(right, y, right, bottom, color);
+    }
+
+    // Text with the top left corner at (x, y), lines are separated with '\n'
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: &Color) {
+        let mut glyph_x = x;
+        let mut glyph_y = y;
+
+        for character in text.chars() {
+            if character == '\n' {
+                glyph_x = x;
+                glyph_y += text::LINE_ADVANCE;
+                continue;
+            }
+
+            for (row, bits) in text::glyph(character).iter().enumerate() {
+                for column in 0..text::GLYPH_WIDTH {
+                    if bits & (1 << (text::GLYPH_WIDTH - 1 - column)) != 0 {
+                        self.point(glyph_x + column, glyph_y + row as i32, color);
+                    }
+                }
+            }
+
+            glyph_x += text::GLYPH_ADVANCE;
+        }
     }
 
     pub fn clear(&mut self, color: &Color) {
-        for vertex in self.img_buf.iter_mut() {
-            *vertex = color.clone();
+        self.clear_background(Background::Solid(*color));
+    }
+
+    pub fn clear_gradient(&mut self, top: &Color, bottom: &Color) {
+        self.clear_background(Background::Gradient { top: *top, bottom: *bottom });
+    }
+
+    fn clear_background(&mut self, background: Background) {
+        let width = self.plane_size.width as usize;
+        for (y, row) in self.img_buf.chunks_mut(width).enumerate() {
+            let color = background.at_row(y as i32, self.plane_size.height);
+            for vertex in row.iter_mut() {
+                *vertex = color;
+            }
         }
+
+        if let Some(buffer) = &mut self.coverage {
+            for coverage in buffer.iter_mut() {
+                *coverage = 0.0;
+            }
+        }
+
+        self.outline = None;
+        self.background = background;
     }
 
-    fn move_cursor_to_origin(&mut self) -> Result<(), Error>{
-        use std::io::Write;
-        self.stdout.write_all(b"\x1B[0;0H")?;
+    // Pixels are given row by row, the image itself is not changed
+    pub fn set_outline(&mut self, color: Color, pixels: Vec<bool>) {
+        self.outline = Some((color, pixels));
+    }
 
-        Ok(())
+    // Number of pixels which would be displayed differently than in the last displayed frame,
+    // including the overlay, the outline and blended edges
+    pub fn changed_pixels(&self) -> usize {
+        if self.displayed_buf.len() != self.img_buf.len() {
+            return self.img_buf.len();
+        }
+
+        let width = self.plane_size.width;
+        self.displayed_buf.iter().enumerate()
+            .filter(|(i, displayed)| {
+                let color = self.resolved_vertex(*i as i32 % width, *i as i32 / width);
+                color.r != displayed.r || color.g != displayed.g || color.b != displayed.b
+            })
+            .count()
     }
 
     pub fn display(&mut self) -> Result<(), Error> {
+        if self.displayed_buf.len() != self.img_buf.len() {
+            self.displayed_buf = Self::create_image_buffer(
+                self.plane_size.width as usize, self.plane_size.height as usize
+            );
+        }
+
         for y in 0..self.plane_size.height {
             for x in 0..self.plane_size.width {
-                // Avoid cloning the color by evading the borrow-checker
-                let color = self.vertex_ref(x, y) as *const Color;
-                unsafe {
-                    self.set_win_vertex(x, y, &*color);
-                }
+                let color = self.resolved_vertex(x, y);
+                self.set_win_vertex(x, y, &color);
+                self.displayed_buf[(x + y * self.plane_size.width) as usize] = color;
             }
         }
 
@@ -130,17 +652,339 @@ impl Drawer {
     }
 
     fn print_window_buffer(&mut self) -> Result<(), Error> {
-        self.move_cursor_to_origin()?;
+        if !self.cursor_hidden {
+            if self.alt_screen {
+                self.pending_frames.extend_from_slice(Self::ENTER_ALT_SCREEN_SEQUENCE);
+            }
+
+            self.pending_frames.extend_from_slice(Self::HIDE_CURSOR_SEQUENCE);
+            self.cursor_hidden = true;
+        }
+
+        self.pending_frames.extend_from_slice(Self::MOVE_TO_ORIGIN_SEQUENCE);
+        self.pending_frames.extend_from_slice(&self.win_buf);
+        self.pending_frame_count += 1;
+        self.output_time = std::time::Duration::ZERO;
 
+        if self.pending_frame_count >= self.flush_interval {
+            let start = std::time::Instant::now();
+            self.flush()?;
+            self.output_time = start.elapsed();
+        }
+
+        Ok(())
+    }
+
+    // Writes out the frames which are still kept in memory
+    pub fn flush(&mut self) -> Result<(), Error> {
         use std::io::Write;
-        self.stdout.write_all(&self.win_buf)?;
-        self.stdout.flush()?;
+        self.writer.write_all(&self.pending_frames)?;
+        self.writer.flush()?;
+        self.pending_frames.clear();
+        self.pending_frame_count = 0;
 
         Ok(())
     }
 
+    pub fn output_time(&self) -> std::time::Duration {
+        self.output_time
+    }
+
+    pub fn to_image(&self) -> Image {
+        let mut buffer = Vec::with_capacity(self.img_buf.len());
+
+        // Images are stored bottom-up
+        for y in (0..self.plane_size.height).rev() {
+            for x in 0..self.plane_size.width {
+                buffer.push(self.resolved_vertex(x, y));
+            }
+        }
+
+        Image::from_colors(buffer, self.plane_size())
+    }
+
     #[inline(always)]
     pub fn plane_size(&self) -> Size {
         self.plane_size.clone()
     }
+
+    pub fn restore_terminal(&mut self) -> Result<(), Error> {
+        use std::io::Write;
+        self.flush()?;
+
+        // Nothing was changed if no frame has been displayed
+        if !self.cursor_hidden {
+            return Ok(());
+        }
+
+        self.writer.write_all(Self::RESTORE_SEQUENCE)?;
+        if self.alt_screen {
+            self.writer.write_all(Self::LEAVE_ALT_SCREEN_SEQUENCE)?;
+        }
+        self.writer.flush()?;
+        self.cursor_hidden = false;
+
+        Ok(())
+    }
+}
+
+impl Default for Drawer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Drawer {
+    fn drop(&mut self) {
+        // Errors cannot be reported while dropping
+        let _ = self.restore_terminal();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Writer whose output can still be read after the drawer took ownership of it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn output(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    // Pixels of the scene with exactly the given color, row by row
+    fn pixels_with_color(drawer: &Drawer, color: &Color) -> Vec<(i32, i32)> {
+        let size = drawer.plane_size();
+        (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| {
+                let pixel = drawer.vertex_ref(x, y);
+                (pixel.r, pixel.g, pixel.b) == (color.r, color.g, color.b)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writes_frame_to_writer() {
+        let buffer = SharedBuffer::default();
+        let mut drawer = Drawer::with_writer(buffer.clone(), WinSize { cols: 3, rows: 2 });
+        drawer.clear(&Color::RED);
+        drawer.display().unwrap();
+
+        let output = buffer.output();
+        let frame = output.strip_prefix("\x1b[?25l\x1B[0;0H").unwrap();
+        let cell = "\x1b[48;2;255;000;000m\x1b[38;2;255;000;000m\u{2584}";
+        assert_eq!(frame, cell.repeat(6));
+    }
+
+    #[test]
+    fn restores_terminal_on_drop() {
+        let buffer = SharedBuffer::default();
+        let mut drawer = Drawer::with_writer(buffer.clone(), WinSize { cols: 3, rows: 2 })
+            .with_alt_screen(true);
+        drawer.display().unwrap();
+        drop(drawer);
+
+        let output = buffer.output();
+        assert!(output.starts_with("\x1b[?1049h\x1b[?25l"));
+        assert!(output.ends_with("\x1b[0m\x1b[2J\x1b[H\x1b[?25h\x1b[?1049l"));
+    }
+
+    #[test]
+    fn leaves_untouched_terminal_alone() {
+        let buffer = SharedBuffer::default();
+        drop(Drawer::with_writer(buffer.clone(), WinSize { cols: 3, rows: 2 }).with_alt_screen(true));
+
+        assert!(buffer.output().is_empty());
+    }
+
+    #[test]
+    fn alt_screen_brackets_output() {
+        let frame_output = |alt_screen| {
+            let buffer = SharedBuffer::default();
+            let mut drawer = Drawer::with_writer(buffer.clone(), WinSize { cols: 3, rows: 2 })
+                .with_alt_screen(alt_screen);
+            drawer.display().unwrap();
+            drop(drawer);
+            buffer.output()
+        };
+
+        let output = frame_output(true);
+        let enter = output.find("\x1b[?1049h").unwrap();
+        let leave = output.find("\x1b[?1049l").unwrap();
+        let frame = output.find('\u{2584}').unwrap();
+        assert!(enter < frame && frame < leave);
+
+        let output = frame_output(false);
+        assert!(!output.contains("\x1b[?1049h") && !output.contains("\x1b[?1049l"));
+    }
+
+    #[test]
+    fn full_block_has_one_pixel_per_cell() {
+        let half_block = Drawer::with_writer(std::io::sink(), WinSize { cols: 8, rows: 5 });
+        assert_eq!((half_block.plane_size().width, half_block.plane_size().height), (8, 10));
+
+        let full_block = Drawer::with_writer(std::io::sink(), WinSize { cols: 8, rows: 5 })
+            .with_glyph_mode(GlyphMode::FullBlock);
+        assert_eq!((full_block.plane_size().width, full_block.plane_size().height), (8, 5));
+    }
+
+    #[test]
+    fn draws_lines_and_rectangles() {
+        let mut drawer = Drawer::with_writer(std::io::sink(), WinSize { cols: 10, rows: 5 });
+        drawer.clear(&Color::BLACK);
+
+        drawer.line(2, 3, 7, 3, &Color::RED);
+        assert_eq!(pixels_with_color(&drawer, &Color::RED), (2..=7).map(|x| (x, 3)).collect::<Vec<_>>());
+
+        // Clipped by the left border of the plane
+        drawer.rect(-1, 5, 5, 4, &Color::GREEN);
+        let outline: Vec<(i32, i32)> = vec![
+            (0, 5), (1, 5), (2, 5), (3, 5),
+            (3, 6),
+            (3, 7),
+            (0, 8), (1, 8), (2, 8), (3, 8)
+        ];
+        assert_eq!(pixels_with_color(&drawer, &Color::GREEN), outline);
+    }
+
+    #[test]
+    fn gradient_runs_from_top_to_bottom_row() {
+        let mut drawer = Drawer::with_writer(std::io::sink(), WinSize { cols: 4, rows: 5 });
+        let (top, bottom) = (Color::rgb(0, 0, 200), Color::rgb(200, 100, 0));
+        drawer.clear_gradient(&top, &bottom);
+
+        let size = drawer.plane_size();
+        let top_row = pixels_with_color(&drawer, &top);
+        let bottom_row = pixels_with_color(&drawer, &bottom);
+        assert_eq!(top_row, (0..size.width).map(|x| (x, 0)).collect::<Vec<_>>());
+        assert_eq!(bottom_row, (0..size.width).map(|x| (x, size.height - 1)).collect::<Vec<_>>());
+
+        // Rows in between are blended
+        let middle = drawer.vertex_ref(0, size.height / 2);
+        assert!(middle.r > 0 && middle.r < 200 && middle.b > 0 && middle.b < 200);
+    }
+
+    #[test]
+    fn draws_text() {
+        let mut drawer = Drawer::with_writer(std::io::sink(), WinSize { cols: 20, rows: 6 });
+        drawer.clear(&Color::BLACK);
+        drawer.draw_text(2, 1, "HI", &Color::WHITE);
+
+        let lit = pixels_with_color(&drawer, &Color::WHITE);
+        // The I glyph starts one column after the 5 columns of H
+        let glyph_i: Vec<&str> = vec![".###.", "..#..", "..#..", "..#..", "..#..", "..#..", ".###."];
+        for (row, line) in glyph_i.iter().enumerate() {
+            for (column, cell) in line.chars().enumerate() {
+                let pixel = (8 + column as i32, 1 + row as i32);
+                assert_eq!(lit.contains(&pixel), cell == '#');
+            }
+        }
+        assert!(lit.contains(&(2, 1)) && lit.contains(&(4, 4)));
+        assert_eq!(lit.len(), 17 + 11);
+    }
+
+    #[test]
+    fn ascii_maps_luminance_to_characters() {
+        let buffer = SharedBuffer::default();
+        let mut drawer = Drawer::with_writer(buffer.clone(), WinSize { cols: 2, rows: 1 }).with_ascii();
+        drawer.clear(&Color::BLACK);
+        drawer.point(0, 0, &Color::WHITE);
+        drawer.display().unwrap();
+
+        assert!(buffer.output().ends_with("@ "));
+    }
+
+    #[test]
+    fn dithering_splits_color_between_palette_indices() {
+        let palette_indices = |dithering: bool| {
+            let buffer = SharedBuffer::default();
+            let mut drawer = Drawer::with_writer(buffer.clone(), WinSize { cols: 2, rows: 1 })
+                .with_color_mode(ColorMode::Ansi256);
+            drawer.set_dithering(dithering);
+            drawer.clear(&Color::rgb(115, 115, 115));
+            drawer.display().unwrap();
+
+            let mut indices: Vec<String> = buffer.output().split(";5;").skip(1)
+                .map(|sequence| sequence[..3].to_string())
+                .collect();
+            indices.sort();
+            indices.dedup();
+            indices
+        };
+
+        assert_eq!(palette_indices(false).len(), 1);
+        assert!(palette_indices(true).len() > 1);
+    }
+
+    #[test]
+    fn zero_terminal_size_falls_back() {
+        let empty = |_: &str| None;
+        assert_eq!(Drawer::size_or_fallback(0, 0, empty), Drawer::DEFAULT_TERMINAL_SIZE);
+        assert_eq!(Drawer::size_or_fallback(100, 0, empty), (100, Drawer::DEFAULT_TERMINAL_SIZE.1));
+
+        let environment = |variable: &str| match variable {
+            "COLUMNS" => Some("120".to_string()),
+            "LINES" => Some("0".to_string()),
+            _ => None
+        };
+        let (cols, rows) = Drawer::size_or_fallback(0, 0, environment);
+        assert_eq!((cols, rows), (120, Drawer::DEFAULT_TERMINAL_SIZE.1));
+
+        let drawer = Drawer::with_writer(std::io::sink(), WinSize { cols: cols as i32, rows: rows as i32 });
+        let size = drawer.plane_size();
+        assert_eq!((size.width, size.height), (120, 2 * rows as i32));
+    }
+
+    #[test]
+    fn flush_interval_batches_frames() {
+        let buffer = SharedBuffer::default();
+        let mut drawer = Drawer::with_writer(buffer.clone(), WinSize { cols: 4, rows: 2 });
+        drawer.set_flush_interval(2);
+        drawer.clear(&Color::BLACK);
+
+        drawer.display().unwrap();
+        assert!(buffer.output().is_empty());
+
+        drawer.display().unwrap();
+        let output = buffer.output();
+        assert!(!output.is_empty());
+
+        // Both frames reach the writer at once
+        assert_eq!(output.matches("\x1B[0;0H").count(), 2);
+    }
+
+    #[test]
+    fn overlay_survives_clear_until_cleared_itself() {
+        let mut drawer = Drawer::with_writer(std::io::sink(), WinSize { cols: 4, rows: 2 });
+        let (hud, scene) = (Color::rgb(200, 0, 0), Color::rgb(0, 200, 0));
+        drawer.set_layer(Layer::Overlay);
+        drawer.point(1, 1, &hud);
+        drawer.set_layer(Layer::Scene);
+        drawer.point(2, 2, &scene);
+
+        drawer.clear(&Color::BLACK);
+        let rgb = |color: Color| (color.r, color.g, color.b);
+        assert_eq!(rgb(drawer.resolved_vertex(1, 1)), (200, 0, 0));
+        assert_eq!(rgb(drawer.resolved_vertex(2, 2)), (0, 0, 0));
+
+        drawer.clear_overlay();
+        assert_eq!(rgb(drawer.resolved_vertex(1, 1)), (0, 0, 0));
+    }
 }
@@ -2,7 +2,10 @@
 pub enum Error {
     Io,
     Parse,
-    UnsupportedFormat
+    UnsupportedFormat,
+    // Texture named by a material library which does not exist,
+    // the path is only read when the error is printed
+    MissingTexture(std::path::PathBuf)
 }
 
 impl From<std::io::Error> for Error {
@@ -1,11 +1,17 @@
 use crate::error::Error;
+use crate::inflate;
 use crate::primitive::{
     Color,
     Size
 };
 
+use std::convert::TryFrom;
+
+#[derive(Clone)]
 pub struct Image {
     buffer: Vec<Color>,
+    // Opacity of every pixel, missing for opaque images
+    alpha: Option<Vec<u8>>,
     size: Size
 }
 
@@ -13,7 +19,8 @@ impl Image {
     const TGA_HEADER_SIZE: usize = 18;
     fn parse_tga_file(
         file_buffer: &[u8],
-        size: &mut Size
+        size: &mut Size,
+        alpha_buffer: &mut Vec<u8>
     ) -> Result<Vec<Color>, Error> {
         if file_buffer.len() <= Self::TGA_HEADER_SIZE {
             return Err(Error::Parse);
@@ -34,15 +41,15 @@ impl Image {
             u16::from(file_buffer[15]) << 0b1000) | u16::from(file_buffer[14])
         ));
 
-        // 0 - do nothing, 1 - ignore last byte of every pixel, otherwise fail
+        // 0 - do nothing, 1 - last byte of every pixel is its alpha, otherwise fail
         let alpha_depth = (file_buffer[17] as usize & 0b1111) / 8;
 
         // 3 BRG bytes + alpha bytes
         let step = 3 + alpha_depth;
 
         Ok(match image_type {
-            2 => Self::load_uncompressed_truecolor(id_length, size, step, file_buffer),
-            10 => Self::load_runlength_encoded_truecolor(id_length, size, step, file_buffer),
+            2 => Self::load_uncompressed_truecolor(id_length, size, step, file_buffer, alpha_buffer),
+            10 => Self::load_runlength_encoded_truecolor(id_length, size, step, file_buffer, alpha_buffer),
             _ => return Err(Error::UnsupportedFormat)
         })
     }
@@ -51,10 +58,10 @@ impl Image {
         id_length: u8,
         size: &Size,
         step: usize,
-        file_buffer: &[u8]
+        file_buffer: &[u8],
+        alpha_buffer: &mut Vec<u8>
     ) -> Vec<Color> {
-        let mut color_buffer =  Vec::<Color>::new();
-        color_buffer.reserve((size.width * size.height) as usize);
+        let mut color_buffer = Vec::<Color>::with_capacity((size.width * size.height) as usize);
         let start = Self::TGA_HEADER_SIZE + id_length as usize;
         let end = start + (size.width * size.height) as usize * step;
         for i in (start..end).step_by(step) {
@@ -64,6 +71,10 @@ impl Image {
                 g: file_buffer[i + 1],
                 b: file_buffer[i]
             });
+
+            if step == 4 {
+                alpha_buffer.push(file_buffer[i + 3]);
+            }
         }
 
         color_buffer
@@ -73,15 +84,17 @@ impl Image {
         id_length: u8,
         size: &Size,
         step: usize,
-        file_buffer: &[u8]
+        file_buffer: &[u8],
+        alpha_buffer: &mut Vec<u8>
     ) -> Vec<Color> {
-        let mut color_buffer =  Vec::<Color>::new();
-        color_buffer.reserve((size.width * size.height) as usize);
+        let mut color_buffer = Vec::<Color>::with_capacity((size.width * size.height) as usize);
         let mut byte_index = Self::TGA_HEADER_SIZE + id_length as usize;
         let mut pixels_read = 0usize;
 
         while pixels_read < (size.width * size.height) as usize {
-            Self::read_encoded_pixels(step, &mut byte_index, &mut pixels_read, file_buffer, &mut color_buffer)
+            Self::read_encoded_pixels(
+                step, &mut byte_index, &mut pixels_read, file_buffer, &mut color_buffer, alpha_buffer
+            )
         }
 
         color_buffer
@@ -92,7 +105,8 @@ impl Image {
         byte_index: &mut usize,
         pixels_read: &mut usize,
         file_buffer: &[u8],
-        color_buffer: &mut Vec<Color>
+        color_buffer: &mut Vec<Color>,
+        alpha_buffer: &mut Vec<u8>
     ) {
         let encoding_type = (file_buffer[*byte_index] & 0b1000_0000) >> 7;
         let encoding_length = (file_buffer[*byte_index] & 0b0111_1111) + 1;
@@ -101,13 +115,13 @@ impl Image {
         // following pixels are not compressed
         if encoding_type == 0 {
             Self::read_uncompressed_pixels(
-                step, encoding_length, pixels_read, byte_index, file_buffer, color_buffer
+                step, encoding_length, pixels_read, byte_index, file_buffer, color_buffer, alpha_buffer
             );
         }
         // following pixels are compressed
         else {
             Self::read_compressed_pixels(
-                step, encoding_length, pixels_read, byte_index, file_buffer, color_buffer
+                step, encoding_length, pixels_read, byte_index, file_buffer, color_buffer, alpha_buffer
             );
         }
     }
@@ -118,7 +132,8 @@ impl Image {
         pixels_read: &mut usize,
         byte_index: &mut usize,
         file_buffer: &[u8],
-        color_buffer: &mut Vec<Color>
+        color_buffer: &mut Vec<Color>,
+        alpha_buffer: &mut Vec<u8>
     ) {
         for j in (0..(step * encoding_length as usize)).step_by(step) {
             color_buffer.push(Color {
@@ -126,6 +141,10 @@ impl Image {
                 g: file_buffer[*byte_index + j + 1],
                 b: file_buffer[*byte_index + j]
             });
+
+            if step == 4 {
+                alpha_buffer.push(file_buffer[*byte_index + j + 3]);
+            }
         }
 
         *pixels_read += encoding_length as usize;
@@ -138,7 +157,8 @@ impl Image {
         pixels_read: &mut usize,
         byte_index: &mut usize,
         file_buffer: &[u8],
-        color_buffer: &mut Vec<Color>
+        color_buffer: &mut Vec<Color>,
+        alpha_buffer: &mut Vec<u8>
     ) {
         for _ in 0..encoding_length {
             color_buffer.push(Color {
@@ -146,28 +166,335 @@ impl Image {
                 g: file_buffer[*byte_index + 1],
                 b: file_buffer[*byte_index]
             });
+
+            if step == 4 {
+                alpha_buffer.push(file_buffer[*byte_index + 3]);
+            }
         }
 
         *pixels_read += encoding_length as usize;
         *byte_index += step;
     }
 
-    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+    const PNG_SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+    fn parse_png_file(
+        file_buffer: &[u8],
+        size: &mut Size
+    ) -> Result<Vec<Color>, Error> {
+        if !file_buffer.starts_with(Self::PNG_SIGNATURE) {
+            return Err(Error::Parse);
+        }
+
+        let mut byte_index = Self::PNG_SIGNATURE.len();
+        let mut channels = 0usize;
+        let mut compressed_data = Vec::<u8>::new();
+
+        loop {
+            // length, type, data and CRC
+            if byte_index + 12 > file_buffer.len() {
+                return Err(Error::Parse);
+            }
+
+            let length = Self::read_be_u32(&file_buffer[byte_index..]) as usize;
+            let chunk_type = &file_buffer[byte_index + 4..byte_index + 8];
+            let data_start = byte_index + 8;
+            let data_end = data_start.checked_add(length).ok_or(Error::Parse)?;
+
+            if data_end + 4 > file_buffer.len() {
+                return Err(Error::Parse);
+            }
+
+            let data = &file_buffer[data_start..data_end];
+
+            match chunk_type {
+                b"IHDR" => channels = Self::parse_png_header(data, size)?,
+                b"IDAT" => compressed_data.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+
+            byte_index = data_end + 4;
+        }
+
+        if channels == 0 {
+            return Err(Error::Parse);
+        }
+
+        let image_data = inflate::zlib_decompress(&compressed_data)?;
+        Self::unfilter_png_scanlines(&image_data, size, channels)
+    }
+
+    fn read_be_u32(bytes: &[u8]) -> u32 {
+        u32::from(bytes[0]) << 24 |
+        u32::from(bytes[1]) << 16 |
+        u32::from(bytes[2]) << 8 |
+        u32::from(bytes[3])
+    }
+
+    // Returns the number of bytes per pixel
+    fn parse_png_header(data: &[u8], size: &mut Size) -> Result<usize, Error> {
+        if data.len() != 13 {
+            return Err(Error::Parse);
+        }
+
+        let width = Self::read_be_u32(&data[0..4]);
+        let height = Self::read_be_u32(&data[4..8]);
+
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(Error::Parse);
+        }
+
+        size.width = width as i32;
+        size.height = height as i32;
+
+        let bit_depth = data[8];
+        let color_type = data[9];
+        let interlace_method = data[12];
+
+        if bit_depth != 8 || interlace_method != 0 {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        match color_type {
+            2 => Ok(3),
+            6 => Ok(4),
+            _ => Err(Error::UnsupportedFormat)
+        }
+    }
+
+    fn unfilter_png_scanlines(
+        image_data: &[u8],
+        size: &Size,
+        channels: usize
+    ) -> Result<Vec<Color>, Error> {
+        let width = size.width as usize;
+        let height = size.height as usize;
+        let stride = width.checked_mul(channels).ok_or(Error::Parse)?;
+
+        // Every scanline is preceded by its filter type byte
+        let data_size = (stride + 1).checked_mul(height).ok_or(Error::Parse)?;
+        if image_data.len() < data_size {
+            return Err(Error::Parse);
+        }
+
+        let mut previous_line = vec![0u8; stride];
+        let mut line = vec![0u8; stride];
+        let mut color_buffer = vec![Color::BLACK; width * height];
+
+        for y in 0..height {
+            let filter_type = image_data[y * (stride + 1)];
+            let filtered = &image_data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+            for i in 0..stride {
+                let a = if i >= channels { line[i - channels] } else { 0 };
+                let b = previous_line[i];
+                let c = if i >= channels { previous_line[i - channels] } else { 0 };
+
+                line[i] = filtered[i].wrapping_add(match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                    4 => Self::paeth_predictor(a, b, c),
+                    _ => return Err(Error::Parse)
+                });
+            }
+
+            // PNG stores rows top-down, while images are kept bottom-up like in TGA files
+            let row = height - 1 - y;
+            for x in 0..width {
+                color_buffer[x + row * width] = Color {
+                    r: line[x * channels],
+                    g: line[x * channels + 1],
+                    b: line[x * channels + 2]
+                };
+            }
+
+            std::mem::swap(&mut line, &mut previous_line);
+        }
+
+        Ok(color_buffer)
+    }
+
+    fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+        let p = i16::from(a) + i16::from(b) - i16::from(c);
+        let pa = (p - i16::from(a)).abs();
+        let pb = (p - i16::from(b)).abs();
+        let pc = (p - i16::from(c)).abs();
+
+        if pa <= pb && pa <= pc {
+            a
+        }
+        else if pb <= pc {
+            b
+        }
+        else {
+            c
+        }
+    }
+
+    const BMP_HEADER_SIZE: usize = 14;
+    const BMP_INFO_HEADER_SIZE: usize = 40;
+    fn parse_bmp_file(
+        file_buffer: &[u8],
+        size: &mut Size
+    ) -> Result<Vec<Color>, Error> {
+        if file_buffer.len() < Self::BMP_HEADER_SIZE + Self::BMP_INFO_HEADER_SIZE ||
+           !file_buffer.starts_with(b"BM") {
+            return Err(Error::Parse);
+        }
+
+        let pixel_data_offset = Self::read_le_u32(&file_buffer[10..]) as usize;
+        let info_header_size = Self::read_le_u32(&file_buffer[14..]) as usize;
+        if info_header_size < Self::BMP_INFO_HEADER_SIZE {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        let width = Self::read_le_u32(&file_buffer[18..]) as i32;
+        let height = Self::read_le_u32(&file_buffer[22..]) as i32;
+        let bits_per_pixel = u16::from(file_buffer[28]) | u16::from(file_buffer[29]) << 8;
+        let compression = Self::read_le_u32(&file_buffer[30..]);
+
+        if compression != 0 || (bits_per_pixel != 24 && bits_per_pixel != 32) {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        // Negative heights mark top-down images, i32::MIN has no positive counterpart
+        if width <= 0 || height == 0 || height == i32::MIN {
+            return Err(Error::Parse);
+        }
+
+        size.width = width;
+        size.height = height.abs();
+
+        let step = bits_per_pixel as usize / 8;
+        // Rows are padded to a multiple of 4 bytes
+        let stride = (width as usize).checked_mul(step).ok_or(Error::Parse)?.div_ceil(4) * 4;
+        let data_end = stride.checked_mul(size.height as usize)
+            .and_then(|data_size| data_size.checked_add(pixel_data_offset))
+            .ok_or(Error::Parse)?;
+        if data_end > file_buffer.len() {
+            return Err(Error::Parse);
+        }
+
+        let mut color_buffer = Vec::<Color>::with_capacity(size.width as usize * size.height as usize);
+        for row in 0..size.height as usize {
+            // Rows are stored bottom-up unless the height is negative
+            let file_row = if height < 0 { size.height as usize - 1 - row } else { row };
+            let start = pixel_data_offset + file_row * stride;
+
+            for i in (start..start + width as usize * step).step_by(step) {
+                // BMP uses BGR(a) color encoding
+                color_buffer.push(Color {
+                    r: file_buffer[i + 2],
+                    g: file_buffer[i + 1],
+                    b: file_buffer[i]
+                });
+            }
+        }
+
+        Ok(color_buffer)
+    }
+
+    fn read_le_u32(bytes: &[u8]) -> u32 {
+        u32::from(bytes[0]) |
+        u32::from(bytes[1]) << 8 |
+        u32::from(bytes[2]) << 16 |
+        u32::from(bytes[3]) << 24
+    }
+
+    fn read_file<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<u8>, Error> {
         let mut file = std::fs::File::open(path)?;
         let mut file_buffer = Vec::<u8>::new();
 
         use std::io::Read;
         file.read_to_end(&mut file_buffer)?;
 
+        Ok(file_buffer)
+    }
+
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        Self::from_tga_bytes(&Self::read_file(path)?)
+    }
+
+    // Parses an in-memory TGA file, for example one included with include_bytes!
+    pub fn from_tga_bytes(file_buffer: &[u8]) -> Result<Self, Error> {
+        let mut image_size = Size { width: 0, height: 0 };
+        let mut alpha_buffer = Vec::<u8>::new();
+        let color_buffer = Self::parse_tga_file(file_buffer, &mut image_size, &mut alpha_buffer)?;
+
+        Ok(Image {
+            buffer: color_buffer,
+            alpha: if alpha_buffer.is_empty() { None } else { Some(alpha_buffer) },
+            size: image_size
+        })
+    }
+
+    pub fn from_png<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        Self::from_png_bytes(&Self::read_file(path)?)
+    }
+
+    pub fn from_png_bytes(file_buffer: &[u8]) -> Result<Self, Error> {
+        let mut image_size = Size { width: 0, height: 0 };
+        let color_buffer = Self::parse_png_file(file_buffer, &mut image_size)?;
+
+        Ok(Image {
+            buffer: color_buffer,
+            alpha: None,
+            size: image_size
+        })
+    }
+
+    pub fn from_bmp<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        Self::from_bmp_bytes(&Self::read_file(path)?)
+    }
+
+    pub fn from_bmp_bytes(file_buffer: &[u8]) -> Result<Self, Error> {
         let mut image_size = Size { width: 0, height: 0 };
-        let color_buffer = Self::parse_tga_file(&file_buffer, &mut image_size)?;
+        let color_buffer = Self::parse_bmp_file(file_buffer, &mut image_size)?;
 
         Ok(Image {
             buffer: color_buffer,
+            alpha: None,
             size: image_size
         })
     }
 
+    pub fn from_colors(buffer: Vec<Color>, size: Size) -> Self {
+        Image { buffer, alpha: None, size }
+    }
+
+    pub fn save_tga<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Error> {
+        let mut file_buffer = Vec::<u8>::with_capacity(
+            Self::TGA_HEADER_SIZE + self.buffer.len() * 3
+        );
+
+        // The header stores the size in 16 bits
+        let width = u16::try_from(self.size.width).map_err(|_| Error::UnsupportedFormat)?;
+        let height = u16::try_from(self.size.height).map_err(|_| Error::UnsupportedFormat)?;
+
+        // No image ID, no color map, uncompressed truecolor
+        file_buffer.extend_from_slice(&[0, 0, 2]);
+        // Empty color map specification
+        file_buffer.extend_from_slice(&[0, 0, 0, 0, 0]);
+        // X and Y origin
+        file_buffer.extend_from_slice(&[0, 0, 0, 0]);
+        file_buffer.extend_from_slice(&width.to_le_bytes());
+        file_buffer.extend_from_slice(&height.to_le_bytes());
+        // 24 bits per pixel, no alpha, bottom-left origin
+        file_buffer.extend_from_slice(&[24, 0]);
+
+        for color in self.buffer.iter() {
+            file_buffer.extend_from_slice(&[color.b, color.g, color.r]);
+        }
+
+        use std::io::Write;
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(&file_buffer)?;
+
+        Ok(())
+    }
+
     pub fn size(&self) -> &Size {
         &self.size
     }
@@ -175,4 +502,230 @@ impl Image {
     pub fn at(&self, x: usize, y: usize) -> &Color {
         &self.buffer[x + y * self.size.width as usize]
     }
+
+    // Pixels of images without an alpha channel are opaque
+    pub fn alpha_at(&self, x: usize, y: usize) -> u8 {
+        match &self.alpha {
+            Some(alpha) => alpha[x + y * self.size.width as usize],
+            None => 255
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+
+    // Averages blocks of 2x2 pixels, odd rows and columns at the edges are dropped.
+    // Dimensions of 1 pixel are not halved
+    pub fn downsample_half(&self) -> Image {
+        let size = Size {
+            width: std::cmp::max(self.size.width / 2, 1),
+            height: std::cmp::max(self.size.height / 2, 1)
+        };
+
+        let mut buffer = Vec::with_capacity((size.width * size.height) as usize);
+        let mut alpha = self.alpha.as_ref().map(|_| Vec::with_capacity(buffer.capacity()));
+
+        for y in 0..size.height as usize {
+            for x in 0..size.width as usize {
+                let x0 = std::cmp::min(2 * x, self.size.width as usize - 1);
+                let x1 = std::cmp::min(2 * x + 1, self.size.width as usize - 1);
+                let y0 = std::cmp::min(2 * y, self.size.height as usize - 1);
+                let y1 = std::cmp::min(2 * y + 1, self.size.height as usize - 1);
+
+                let block = [self.at(x0, y0), self.at(x1, y0), self.at(x0, y1), self.at(x1, y1)];
+                let average = |channel: fn(&Color) -> u8| {
+                    let sum: u32 = block.iter().map(|color| u32::from(channel(color))).sum();
+                    ((sum + 2) / 4) as u8
+                };
+
+                buffer.push(Color {
+                    r: average(|color| color.r),
+                    g: average(|color| color.g),
+                    b: average(|color| color.b)
+                });
+
+                if let Some(alpha) = &mut alpha {
+                    let sum: u32 = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].iter()
+                        .map(|&(x, y)| u32::from(self.alpha_at(x, y)))
+                        .sum();
+                    alpha.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+
+        Image { buffer, alpha, size }
+    }
+
+    // Mipmap chain starting with a copy of the image and ending with a 1x1 image
+    pub fn generate_mipmaps(&self) -> Vec<Image> {
+        let mut mipmaps = vec![self.clone()];
+
+        loop {
+            let last = &mipmaps[mipmaps.len() - 1];
+            if last.size.width <= 1 && last.size.height <= 1 {
+                break;
+            }
+
+            let next = last.downsample_half();
+            mipmaps.push(next);
+        }
+
+        mipmaps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2 RGB image, red and green in the top row, blue and white in the bottom one.
+    // The bottom row is stored with the Sub filter.
+    const TINY_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d,
+        0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02,
+        0x08, 0x02, 0x00, 0x00, 0x00, 0xfd, 0xd4, 0x9a, 0x73, 0x00, 0x00, 0x00,
+        0x15, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0xc0,
+        0xf0, 0x9f, 0x81, 0x11, 0x48, 0xfc, 0xff, 0xcf, 0x00, 0x00, 0x1e, 0xf6,
+        0x04, 0xfd, 0x86, 0x5d, 0x5b, 0xa9, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+        0x4e, 0x44, 0xae, 0x42, 0x60, 0x82
+    ];
+
+    fn rgb(color: &Color) -> (u8, u8, u8) {
+        (color.r, color.g, color.b)
+    }
+
+    #[test]
+    fn decodes_tiny_png() {
+        let image = Image::from_png_bytes(TINY_PNG).unwrap();
+
+        assert_eq!((image.size().width, image.size().height), (2, 2));
+        // Images are kept bottom-up
+        assert_eq!(rgb(image.at(0, 1)), (255, 0, 0));
+        assert_eq!(rgb(image.at(1, 1)), (0, 255, 0));
+        assert_eq!(rgb(image.at(0, 0)), (0, 0, 255));
+        assert_eq!(rgb(image.at(1, 0)), (255, 255, 255));
+    }
+
+    #[test]
+    fn rejects_png_with_invalid_size() {
+        let mut zero_width = TINY_PNG.to_vec();
+        zero_width[16..20].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(Image::from_png_bytes(&zero_width).is_err());
+
+        let mut huge_height = TINY_PNG.to_vec();
+        huge_height[20..24].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(Image::from_png_bytes(&huge_height).is_err());
+    }
+
+    // 24-bit BMP with the given rows of BGR bytes, bottom row first
+    fn bmp_24(width: i32, height: i32, rows: &[&[u8]]) -> Vec<u8> {
+        let mut file = b"BM".to_vec();
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&54u32.to_le_bytes());
+        file.extend_from_slice(&40u32.to_le_bytes());
+        file.extend_from_slice(&width.to_le_bytes());
+        file.extend_from_slice(&height.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&24u16.to_le_bytes());
+        file.extend_from_slice(&[0; 24]);
+
+        for row in rows {
+            file.extend_from_slice(row);
+            // Padding to a multiple of 4 bytes
+            file.resize(file.len() + (4 - row.len() % 4) % 4, 0);
+        }
+
+        file
+    }
+
+    #[test]
+    fn decodes_24_bit_bmp() {
+        let bmp = bmp_24(2, 2, &[
+            &[0, 0, 255, 0, 255, 0],
+            &[255, 0, 0, 255, 255, 255]
+        ]);
+        let image = Image::from_bmp_bytes(&bmp).unwrap();
+
+        assert_eq!((image.size().width, image.size().height), (2, 2));
+        assert_eq!(rgb(image.at(0, 0)), (255, 0, 0));
+        assert_eq!(rgb(image.at(1, 1)), (255, 255, 255));
+    }
+
+    #[test]
+    fn rejects_bmp_with_invalid_size() {
+        assert!(Image::from_bmp_bytes(&bmp_24(0, 2, &[])).is_err());
+        assert!(Image::from_bmp_bytes(&bmp_24(2, i32::MIN, &[])).is_err());
+        assert!(Image::from_bmp_bytes(&bmp_24(i32::MAX, i32::MAX, &[])).is_err());
+    }
+
+    #[test]
+    fn saved_tga_loads_back() {
+        let colors = vec![
+            Color { r: 10, g: 20, b: 30 },
+            Color { r: 40, g: 50, b: 60 },
+            Color { r: 70, g: 80, b: 90 },
+            Color { r: 100, g: 110, b: 120 },
+            Color { r: 130, g: 140, b: 150 },
+            Color { r: 160, g: 170, b: 180 }
+        ];
+        let image = Image::from_colors(colors.clone(), Size { width: 3, height: 2 });
+
+        let path = std::env::temp_dir().join(format!("reindeer-save-{}.tga", std::process::id()));
+        image.save_tga(&path).unwrap();
+        let loaded = Image::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!((loaded.size().width, loaded.size().height), (3, 2));
+        for (i, color) in colors.iter().enumerate() {
+            assert_eq!(rgb(loaded.at(i % 3, i / 3)), rgb(color));
+        }
+    }
+
+    #[test]
+    fn refuses_to_save_tga_wider_than_header_allows() {
+        let image = Image::from_colors(vec![Color::BLACK; 65536], Size { width: 65536, height: 1 });
+        let path = std::env::temp_dir().join(format!("reindeer-wide-{}.tga", std::process::id()));
+
+        assert!(matches!(image.save_tga(&path), Err(Error::UnsupportedFormat)));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn reads_tga_from_memory() {
+        // Uncompressed truecolor 2x1 image with BGR pixels
+        let mut tga = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 1, 0, 24, 0];
+        tga.extend_from_slice(&[30, 20, 10, 60, 50, 40]);
+        let image = Image::from_tga_bytes(&tga).unwrap();
+
+        assert_eq!((image.size().width, image.size().height), (2, 1));
+        assert_eq!(rgb(image.at(0, 0)), (10, 20, 30));
+        assert_eq!(rgb(image.at(1, 0)), (40, 50, 60));
+        assert!(!image.has_alpha());
+    }
+
+    #[test]
+    fn downsamples_solid_image() {
+        let color = Color { r: 10, g: 200, b: 77 };
+        let image = Image::from_colors(vec![color; 16], Size { width: 4, height: 4 });
+        let half = image.downsample_half();
+
+        assert_eq!((half.size().width, half.size().height), (2, 2));
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(rgb(half.at(x, y)), (10, 200, 77));
+            }
+        }
+    }
+
+    #[test]
+    fn mipmaps_of_narrow_image_end_at_one_pixel() {
+        let image = Image::from_colors(vec![Color::WHITE; 8], Size { width: 2, height: 4 });
+        let sizes: Vec<(i32, i32)> = image.generate_mipmaps().iter()
+            .map(|mipmap| (mipmap.size().width, mipmap.size().height))
+            .collect();
+
+        assert_eq!(sizes, vec![(2, 4), (1, 2), (1, 1)]);
+    }
 }
@@ -0,0 +1,296 @@
+use crate::error::Error;
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    byte_index: usize,
+    bit_index: u32
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            byte_index: 0,
+            bit_index: 0
+        }
+    }
+
+    fn bit(&mut self) -> Result<u32, Error> {
+        if self.byte_index >= self.data.len() {
+            return Err(Error::Parse);
+        }
+
+        let bit = u32::from(self.data[self.byte_index] >> self.bit_index) & 1;
+        self.bit_index += 1;
+        if self.bit_index == 8 {
+            self.bit_index = 0;
+            self.byte_index += 1;
+        }
+
+        Ok(bit)
+    }
+
+    // Deflate stores multi-bit values starting from the least significant bit
+    fn bits(&mut self, count: u32) -> Result<u32, Error> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit_index != 0 {
+            self.bit_index = 0;
+            self.byte_index += 1;
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        if self.byte_index >= self.data.len() {
+            return Err(Error::Parse);
+        }
+
+        self.byte_index += 1;
+        Ok(self.data[self.byte_index - 1])
+    }
+}
+
+// Canonical Huffman code described by the number of codes of every length
+struct Huffman {
+    counts: [u16; Self::MAX_BITS + 1],
+    symbols: Vec<u16>
+}
+
+impl Huffman {
+    const MAX_BITS: usize = 15;
+
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; Self::MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; Self::MAX_BITS + 2];
+        for length in 1..=Self::MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Error> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for length in 1..=Self::MAX_BITS {
+            code |= reader.bit()? as i32;
+            let count = i32::from(self.counts[length]);
+
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(Error::Parse)
+    }
+}
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0
+];
+
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13
+];
+
+// Order in which code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15
+];
+
+fn inflate_stored_block(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), Error> {
+    reader.align_to_byte();
+
+    let length = u16::from(reader.byte()?) | (u16::from(reader.byte()?) << 8);
+    let length_complement = u16::from(reader.byte()?) | (u16::from(reader.byte()?) << 8);
+    if length != !length_complement {
+        return Err(Error::Parse);
+    }
+
+    for _ in 0..length {
+        output.push(reader.byte()?);
+    }
+
+    Ok(())
+}
+
+fn fixed_huffman() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8
+        };
+    }
+
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_huffman(reader: &mut BitReader) -> Result<(Huffman, Huffman), Error> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::<u8>::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_huffman.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + reader.bits(2)?),
+                None => return Err(Error::Parse)
+            },
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return Err(Error::Parse)
+        };
+
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+
+    if lengths.len() != literal_count + distance_count {
+        return Err(Error::Parse);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..])
+    ))
+}
+
+fn inflate_huffman_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman
+) -> Result<(), Error> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let length_index = symbol - 257;
+        if length_index >= LENGTH_BASES.len() {
+            return Err(Error::Parse);
+        }
+        let length = LENGTH_BASES[length_index] as usize +
+            reader.bits(u32::from(LENGTH_EXTRA_BITS[length_index]))? as usize;
+
+        let distance_index = distances.decode(reader)? as usize;
+        if distance_index >= DISTANCE_BASES.len() {
+            return Err(Error::Parse);
+        }
+        let distance = DISTANCE_BASES[distance_index] as usize +
+            reader.bits(u32::from(DISTANCE_EXTRA_BITS[distance_index]))? as usize;
+
+        if distance > output.len() {
+            return Err(Error::Parse);
+        }
+
+        // The copied range may overlap the bytes being written
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::<u8>::new();
+
+    loop {
+        let last_block = reader.bit()? == 1;
+
+        match reader.bits(2)? {
+            0 => inflate_stored_block(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_huffman();
+                inflate_huffman_block(&mut reader, &mut output, &literals, &distances)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_huffman(&mut reader)?;
+                inflate_huffman_block(&mut reader, &mut output, &literals, &distances)?;
+            },
+            _ => return Err(Error::Parse)
+        }
+
+        if last_block {
+            return Ok(output);
+        }
+    }
+}
+
+// Zlib stream: 2 byte header, deflate data and Adler-32 checksum
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 6 {
+        return Err(Error::Parse);
+    }
+
+    let compression_method = data[0] & 0b1111;
+    let preset_dictionary = data[1] & 0b0010_0000 != 0;
+    if (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 != 0 {
+        return Err(Error::Parse);
+    }
+
+    if compression_method != 8 || preset_dictionary {
+        return Err(Error::UnsupportedFormat);
+    }
+
+    inflate(&data[2..])
+}
@@ -0,0 +1,125 @@
+use crate::error::Error;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+// Puts the terminal in non-blocking raw mode, restoring the original mode when dropped
+pub struct Keyboard {
+    original_mode: libc::termios
+}
+
+impl Keyboard {
+    pub fn new() -> Result<Self, Error> {
+        let original_mode = unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return Err(Error::Io);
+            }
+
+            termios
+        };
+
+        let mut raw_mode = original_mode;
+        raw_mode.c_lflag &= !(libc::ICANON | libc::ECHO);
+        // Reading returns immediately, even if no key was pressed
+        raw_mode.c_cc[libc::VMIN] = 0;
+        raw_mode.c_cc[libc::VTIME] = 0;
+
+        unsafe {
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw_mode) != 0 {
+                return Err(Error::Io);
+            }
+        }
+
+        Ok(Keyboard { original_mode })
+    }
+
+    fn read_input(&self) -> Result<Vec<u8>, Error> {
+        let mut input = Vec::<u8>::new();
+        let mut buf = [0u8; 32];
+
+        loop {
+            let read = unsafe {
+                libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+            };
+
+            if read < 0 {
+                return Err(Error::Io);
+            }
+
+            if read == 0 {
+                return Ok(input);
+            }
+
+            input.extend_from_slice(&buf[..read as usize]);
+        }
+    }
+
+    pub fn pressed_keys(&self) -> Result<Vec<Key>, Error> {
+        Ok(decode_keys(&self.read_input()?))
+    }
+}
+
+// An escape sequence cut off at the end of the input is dropped
+pub fn decode_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::<Key>::new();
+    let mut i = 0;
+
+    while i < input.len() {
+        // Arrow keys are sent as "\x1b[A" to "\x1b[D"
+        if input[i] == 0x1b && i + 1 < input.len() && input[i + 1] == b'[' {
+            if i + 2 >= input.len() {
+                break;
+            }
+
+            match input[i + 2] {
+                b'A' => keys.push(Key::Up),
+                b'B' => keys.push(Key::Down),
+                b'C' => keys.push(Key::Right),
+                b'D' => keys.push(Key::Left),
+                _ => {}
+            }
+
+            i += 3;
+            continue;
+        }
+
+        keys.push(Key::Char(char::from(input[i])));
+        i += 1;
+    }
+
+    keys
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original_mode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_arrows_between_characters() {
+        assert_eq!(
+            decode_keys(b"w\x1b[A\x1b[B\x1b[C\x1b[DQ"),
+            vec![Key::Char('w'), Key::Up, Key::Down, Key::Right, Key::Left, Key::Char('Q')]
+        );
+    }
+
+    #[test]
+    fn drops_truncated_escape_sequence() {
+        assert_eq!(decode_keys(b"a\x1b["), vec![Key::Char('a')]);
+        assert_eq!(decode_keys(b"\x1b[A\x1b"), vec![Key::Up, Key::Char('\x1b')]);
+    }
+}
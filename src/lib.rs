@@ -0,0 +1,21 @@
+#![warn(clippy::all)]
+
+pub mod primitive;
+pub mod mesh;
+pub mod image;
+mod inflate;
+pub mod error;
+pub mod drawer;
+pub mod renderer;
+pub mod transform;
+pub mod vector;
+pub mod matrix;
+pub mod quat;
+pub mod keyboard;
+pub mod camera;
+pub mod light;
+pub mod material;
+pub mod scene;
+pub mod shader;
+pub mod terminal;
+mod text;
@@ -0,0 +1,32 @@
+use crate::vector::Vec3;
+
+#[derive(Clone, Copy)]
+pub enum Light {
+    // Light coming from the `dir` direction, like the sun
+    Directional {
+        dir: Vec3
+    },
+    // Light emitted from a single point and fading with the distance
+    Point {
+        position: Vec3,
+        constant: f32,
+        linear: f32,
+        quadratic: f32
+    }
+}
+
+impl Light {
+    // Returns the normalized direction towards the light and its attenuation at `point`
+    pub fn incidence(&self, point: &Vec3) -> (Vec3, f32) {
+        match self {
+            Light::Directional { dir } => (dir.normalized(), 1.0),
+            Light::Point { position, constant, linear, quadratic } => {
+                let to_light = *position - *point;
+                let distance = to_light.len();
+                let attenuation = 1.0 / (constant + linear * distance + quadratic * distance * distance);
+
+                (to_light.normalized(), attenuation)
+            }
+        }
+    }
+}
@@ -1,61 +1,160 @@
 #![warn(clippy::all)]
 
-mod primitive;
-mod mesh;
-mod image;
-mod error;
-mod drawer;
-mod renderer;
-mod transform;
-mod vector;
-mod matrix;
-
-use crate::error::Error;
-use crate::primitive::Color;
-use crate::renderer::Renderer;
-use crate::mesh::Mesh;
-use crate::image::Image;
-use crate::vector::Vec3;
+mod bench;
+
+use reindeer::camera::Camera;
+use reindeer::error::Error;
+use reindeer::image::Image;
+use reindeer::material::Material;
+use reindeer::mesh::Mesh;
+use reindeer::renderer::Renderer;
+use reindeer::terminal;
+use reindeer::scene::{
+    Model,
+    Scene
+};
+use reindeer::keyboard::{
+    Key,
+    Keyboard
+};
+use reindeer::vector::{
+    Vec3,
+    cross
+};
+
+const MOVE_SPEED: f32 = 0.1;
+const TURN_SPEED: f32 = 0.05;
+// Time without a key press after which the animated camera takes over again
+const IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// Used with the demo model if no scene file is given as the first argument
+const DEFAULT_SCENE: &str = "
+background 0 0 255
+camera 0.5 0.3 1.0 0 0 0
+light 2 5 1
+";
+
+// The demo head is read from the working directory,
+// unless it is embedded in the binary with the embedded-assets feature
+fn demo_model() -> Result<Model, Error> {
+    #[cfg(feature = "embedded-assets")]
+    let (mut mesh, texture, normal_map) = (
+        Mesh::from_obj_str(include_str!("../head.obj"))?,
+        Image::from_tga_bytes(include_bytes!("../head_diffuse.tga"))?,
+        Image::from_tga_bytes(include_bytes!("../head_nm_tangent.tga"))?
+    );
+
+    #[cfg(not(feature = "embedded-assets"))]
+    let (mut mesh, texture, normal_map) = (
+        Mesh::from_file("head.obj")?,
+        Image::from_file("head_diffuse.tga")?,
+        Image::from_file("head_nm_tangent.tga")?
+    );
+
+    mesh.center_and_normalize();
+
+    Ok(Model {
+        mesh,
+        material: Material::new(texture).with_normal_map(normal_map),
+        position: Vec3::ZERO
+    })
+}
+
+fn look_direction(yaw: f32, pitch: f32) -> Vec3 {
+    Vec3 {
+        x: yaw.sin() * pitch.cos(),
+        y: pitch.sin(),
+        z: -yaw.cos() * pitch.cos()
+    }
+}
 
 // Demo scene setup
 fn main() -> Result<(), Error> {
+    if std::env::args().nth(1).as_deref() == Some("--bench") {
+        bench::run(&demo_model()?);
+        return Ok(());
+    }
+
+    terminal::install_interrupt_handler();
     let mut renderer = Renderer::new();
 
     // Animation variables
-    let mut v: f32 = 0.0;
-    let mut u: f32 = 0.0;
-    let mut w: f32 = 0.0;
-    let dynamic_camera = true;
-
-    let light_vector = Vec3 { x: 2.0, y: 5.0, z: 1.0 }.normalized();
-    renderer.light(&light_vector);
-
-    let camera_position = Vec3 { x: 0.5, y: 0.3, z: 1.0 };
-    renderer.camera(
-        &camera_position,
-        &Vec3::ZERO,
-        &Vec3 { x: 0.0, y: 1.0, z: 0.0 }
-    );
+    let mut yaw_animation: f32 = 0.0;
+    let mut pitch_animation: f32 = 0.0;
+    let mut last_key_press: Option<std::time::Instant> = None;
+
+    let (scene, models) = match std::env::args().nth(1) {
+        Some(path) => {
+            let scene = Scene::from_file(path)?;
+            let models = scene.load_models()?;
+            (scene, models)
+        },
+        None => (Scene::from_reader(DEFAULT_SCENE.as_bytes())?, vec![demo_model()?])
+    };
+    scene.configure(&mut renderer);
+    // Only the camera moves
+    renderer.set_static_shadows(true);
+    renderer.set_adaptive_pacing(true);
+
+    let up = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
+    let initial_camera = scene.camera.unwrap_or_else(|| Camera::orbit(Vec3::ZERO, 1.5, 0.0, 0.0));
+
+    // Keyboard controlled camera, used after a key press until the keyboard is left idle
+    let mut eye = initial_camera.eye;
+    let initial_direction = (initial_camera.target - initial_camera.eye).normalized();
+    let mut yaw = initial_direction.x.atan2(-initial_direction.z);
+    let mut pitch = initial_direction.y.asin();
+
+    // Keyboard input is not available if stdin is not a terminal
+    let keyboard = Keyboard::new().ok();
+
+    'render: while !terminal::interrupted() {
+        if let Some(keyboard) = &keyboard {
+            for key in keyboard.pressed_keys()? {
+                last_key_press = Some(std::time::Instant::now());
+
+                let direction = look_direction(yaw, pitch);
+                let right = cross(&direction, &up).normalized();
 
-    let head_mesh = Mesh::from_file("head.obj")?;
-    let head_texture = Image::from_file("head_diffuse.tga")?;
-    let head_normal_map = Image::from_file("head_nm_tangent.tga")?;
-
-    loop {
-        if dynamic_camera {
-            v += 0.06;
-            u -= 0.1;
-            w += 0.03;
-
-            renderer.camera(
-                &Vec3 { x: v.cos() * u.sin(), y: 1.0, z: w.sin() * 2.5 },
-                &Vec3 { x: v.cos(), y: u.sin(), z: w.cos() },
-                &Vec3 { x: 0.0, y: 1.0, z: 0.0 }
-            );
+                // Shift and Caps Lock do not matter
+                let key = match key {
+                    Key::Char(c) => Key::Char(c.to_ascii_lowercase()),
+                    key => key
+                };
+
+                match key {
+                    Key::Char('q') => break 'render,
+                    Key::Char('w') => eye = eye + direction * MOVE_SPEED,
+                    Key::Char('s') => eye = eye - direction * MOVE_SPEED,
+                    Key::Char('a') => eye = eye - right * MOVE_SPEED,
+                    Key::Char('d') => eye = eye + right * MOVE_SPEED,
+                    Key::Left => yaw -= TURN_SPEED,
+                    Key::Right => yaw += TURN_SPEED,
+                    Key::Up => pitch = (pitch + TURN_SPEED).min(1.5),
+                    Key::Down => pitch = (pitch - TURN_SPEED).max(-1.5),
+                    _ => {}
+                }
+            }
         }
 
-        renderer.refresh(&Color::BLUE);
-        renderer.model(&head_mesh, &head_texture, &head_normal_map, &Vec3 {x: 0.0, y: 0.0, z: 0.0});
+        if last_key_press.is_none_or(|time| time.elapsed() > IDLE_TIMEOUT) {
+            yaw_animation += 0.03;
+            pitch_animation += 0.05;
+
+            renderer.set_camera(&Camera::orbit(
+                initial_camera.target, 1.5, yaw_animation, 0.6 * pitch_animation.sin()
+            ));
+        }
+        else {
+            renderer.set_camera(&Camera::new(eye, eye + look_direction(yaw, pitch), up));
+        }
+
+        renderer.refresh();
+        for model in models.iter() {
+            renderer.model(&model.mesh, &model.material, &model.position);
+        }
         renderer.display()?;
     }
+
+    Ok(())
 }
@@ -0,0 +1,164 @@
+use crate::error::Error;
+use crate::image::Image;
+use crate::primitive::{
+    Color,
+    Size
+};
+
+use std::path::Path;
+
+// Surface properties used by PhongShader
+pub struct Material {
+    pub diffuse: Image,
+    // Tangent space normal map, interpolated normal vectors are used if missing
+    pub normal: Option<Image>,
+    // Brightness of the map scales the specular light, white keeps all of it
+    pub specular: Option<Image>,
+    // Color added regardless of lighting
+    pub emissive: Option<Image>,
+    // Multiplies the diffuse color
+    pub base_color: Color,
+    // Exponent of the specular light, greater values give smaller highlights
+    pub shininess: f32
+}
+
+impl Material {
+    pub const DEFAULT_SHININESS: f32 = 35.0;
+
+    pub fn new(diffuse: Image) -> Self {
+        Material {
+            diffuse,
+            normal: None,
+            specular: None,
+            emissive: None,
+            base_color: Color::WHITE,
+            shininess: Self::DEFAULT_SHININESS
+        }
+    }
+
+    pub fn with_normal_map(mut self, normal: Image) -> Self {
+        self.normal = Some(normal);
+        self
+    }
+
+    pub fn with_specular_map(mut self, specular: Image) -> Self {
+        self.specular = Some(specular);
+        self
+    }
+
+    pub fn with_emissive_map(mut self, emissive: Image) -> Self {
+        self.emissive = Some(emissive);
+        self
+    }
+
+    pub fn with_base_color(mut self, base_color: Color) -> Self {
+        self.base_color = base_color;
+        self
+    }
+
+    pub fn with_shininess(mut self, shininess: f32) -> Self {
+        self.shininess = shininess;
+        self
+    }
+
+    // Reads the first material of an MTL file, textures are resolved relative to it
+    pub fn from_mtl_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let directory = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        let file = std::fs::File::open(&path)?;
+        Self::from_mtl_reader(std::io::BufReader::new(file), directory)
+    }
+
+    // Supports Kd, Ns, map_Kd, map_Bump (or bump), map_Ks and map_Ke statements,
+    // materials without map_Kd are white
+    pub fn from_mtl_reader<R: std::io::BufRead>(reader: R, directory: &Path) -> Result<Self, Error> {
+        let white = Image::from_colors(vec![Color::WHITE], Size { width: 1, height: 1 });
+        let mut material = Material::new(white);
+        let mut materials_found = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            let line: Vec<&str> = line.split_whitespace().collect();
+            if line.is_empty() || line[0].starts_with('#') {
+                continue;
+            }
+
+            if line[0] == "newmtl" {
+                materials_found += 1;
+                if materials_found > 1 {
+                    break;
+                }
+
+                continue;
+            }
+
+            match line[0] {
+                "Kd" if line.len() == 4 => {
+                    let channel = |value: &str| -> Result<u8, Error> {
+                        Ok((value.parse::<f32>()?.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    };
+
+                    material.base_color = Color {
+                        r: channel(line[1])?,
+                        g: channel(line[2])?,
+                        b: channel(line[3])?
+                    };
+                },
+                "Ns" if line.len() == 2 => material.shininess = line[1].parse::<f32>()?,
+                // Options preceding the file name are not supported
+                "map_Kd" => material.diffuse = Self::load_texture(directory, &line)?,
+                "map_Bump" | "bump" => material.normal = Some(Self::load_texture(directory, &line)?),
+                "map_Ks" => material.specular = Some(Self::load_texture(directory, &line)?),
+                "map_Ke" => material.emissive = Some(Self::load_texture(directory, &line)?),
+                _ => {}
+            }
+        }
+
+        Ok(material)
+    }
+
+    fn load_texture(directory: &Path, line: &[&str]) -> Result<Image, Error> {
+        if line.len() < 2 {
+            return Err(Error::Parse);
+        }
+
+        let path = directory.join(line[1..].join(" "));
+        if !path.is_file() {
+            return Err(Error::MissingTexture(path));
+        }
+
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => Image::from_png(path),
+            Some("bmp") => Image::from_bmp(path),
+            Some("tga") => Image::from_file(path),
+            _ => Err(Error::UnsupportedFormat)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attaches_tga_texture_named_by_mtl() {
+        let directory = std::env::temp_dir().join(format!("reindeer-mtl-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let texture = Image::from_colors(vec![Color { r: 10, g: 200, b: 30 }; 4], Size { width: 2, height: 2 });
+        texture.save_tga(directory.join("diffuse.TGA")).unwrap();
+        std::fs::write(directory.join("model.mtl"), "newmtl model\nKd 1 0.5 0\nmap_Kd diffuse.TGA\n").unwrap();
+
+        let material = Material::from_mtl_file(directory.join("model.mtl"));
+        std::fs::remove_dir_all(&directory).unwrap();
+        let material = material.unwrap();
+
+        assert_eq!((material.diffuse.size().width, material.diffuse.size().height), (2, 2));
+        let texel = material.diffuse.at(1, 1);
+        assert_eq!((texel.r, texel.g, texel.b), (10, 200, 30));
+        assert_eq!((material.base_color.r, material.base_color.g, material.base_color.b), (255, 128, 0));
+    }
+}
@@ -4,9 +4,29 @@ use crate::vector::{
     Vec4
 };
 
+macro_rules! impl_matrix_vector_mul {
+    ($matrix:ident, $vector:ident) => {
+        impl std::ops::Mul<$vector> for $matrix {
+            type Output = $vector;
+
+            fn mul(self, rhs: $vector) -> Self::Output {
+                let mut v = $vector::ZERO;
+
+                for i in 0..Self::N {
+                    for j in 0..Self::N {
+                        v[i] += self[(i, j)] * rhs[j];
+                    }
+                }
+
+                v
+            }
+        }
+    }
+}
+
 macro_rules! declare_square_matrix {
     ($name:ident, $n:expr) => {
-        #[derive(Clone, Copy)]
+        #[derive(Clone, Copy, PartialEq)]
         pub struct $name {
             buf: [f32; Self::N * Self::N]
         }
@@ -15,9 +35,7 @@ macro_rules! declare_square_matrix {
             pub const N: usize = $n;
 
             pub fn new(rcs: [[f32; Self::N]; Self::N]) -> Self {
-                let mut buf: [f32; Self::N * Self::N] = unsafe {
-                    std::mem::MaybeUninit::uninit().assume_init()
-                };
+                let mut buf = [0.0; Self::N * Self::N];
                 for i in 0..Self::N {
                     for j in 0..Self::N {
                         buf[i * Self::N + j] = rcs[i][j];
@@ -26,6 +44,46 @@ macro_rules! declare_square_matrix {
 
                 $name { buf }
             }
+
+            pub fn approx_eq(&self, other: &$name, epsilon: f32) -> bool {
+                self.buf.iter()
+                    .zip(other.buf.iter())
+                    .all(|(a, b)| (a - b).abs() <= epsilon)
+            }
+        }
+
+        // Prints the rows as nested lists
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{} ", stringify!($name))?;
+                f.debug_list().entries(self.buf.chunks(Self::N)).finish()
+            }
+        }
+
+        // Prints every row in a separate line with the columns aligned to the right,
+        // elements have 3 decimal places unless a different precision is given
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                let precision = f.precision().unwrap_or(3);
+                let elements: Vec<String> = self.buf.iter()
+                    .map(|el| format!("{:.*}", precision, el))
+                    .collect();
+                let width = elements.iter().map(|el| el.len()).max().unwrap_or(0);
+
+                for (i, row) in elements.chunks(Self::N).enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+
+                    write!(f, "[")?;
+                    for el in row {
+                        write!(f, " {:>width$}", el, width = width)?;
+                    }
+                    write!(f, " ]")?;
+                }
+
+                Ok(())
+            }
         }
 
         impl std::ops::Index<(usize, usize)> for $name {
@@ -123,22 +181,7 @@ impl Matrix2 {
     }
 }
 
-impl std::ops::Mul<Vec2> for Matrix2 {
-    type Output = Vec2;
-
-    fn mul(self, rhs: Vec2) -> Self::Output {
-        let mut v: [f32; 2] = unsafe { std::mem::MaybeUninit::uninit().assume_init() };
-
-        for i in 0..2 {
-            v[i] = rhs.x * self[(i, 0)] + rhs.y * self[(i, 1)];
-        }
-
-        Vec2 {
-            x: v[0],
-            y: v[1]
-        }
-    }
-}
+impl_matrix_vector_mul!(Matrix2, Vec2);
 
 declare_square_matrix!(Matrix3, 3);
 
@@ -185,28 +228,7 @@ impl Matrix3 {
     }
 }
 
-impl std::ops::Mul<Vec3> for Matrix3 {
-    type Output = Vec3;
-
-    fn mul(self, rhs: Vec3) -> Self::Output {
-        let mut v: [f32; 3] = unsafe {
-            std::mem::MaybeUninit::uninit().assume_init()
-        };
-
-        for i in 0..3 {
-            v[i] =
-                rhs.x * self[(i, 0)] +
-                rhs.y * self[(i, 1)] +
-                rhs.z * self[(i, 2)];
-        }
-
-        Vec3 {
-            x: v[0],
-            y: v[1],
-            z: v[2]
-        }
-    }
-}
+impl_matrix_vector_mul!(Matrix3, Vec3);
 
 declare_square_matrix!(Matrix4, 4);
 
@@ -219,29 +241,78 @@ impl Matrix4 {
             0.0, 0.0, 0.0, 1.0
         ]
     };
-}
 
-impl std::ops::Mul<Vec4> for Matrix4 {
-    type Output = Vec4;
+    // Gauss-Jordan elimination with partial pivoting
+    pub fn inverse(&self) -> Option<Self> {
+        let mut matrix = *self;
+        let mut inverse = Self::IDENTITY;
+
+        for column in 0..4 {
+            let mut pivot = column;
+            for row in column + 1..4 {
+                if matrix[(row, column)].abs() > matrix[(pivot, column)].abs() {
+                    pivot = row;
+                }
+            }
 
-    fn mul(self, rhs: Vec4) -> Self::Output {
-        let mut v: [f32; 4] = unsafe {
-            std::mem::MaybeUninit::uninit().assume_init()
-        };
+            if matrix[(pivot, column)] == 0.0 {
+                return None;
+            }
 
-        for i in 0..4 {
-            v[i] =
-                rhs.x * self[(i, 0)] +
-                rhs.y * self[(i, 1)] +
-                rhs.z * self[(i, 2)] +
-                rhs.w * self[(i, 3)];
-        }
+            for k in 0..4 {
+                matrix.buf.swap(pivot * 4 + k, column * 4 + k);
+                inverse.buf.swap(pivot * 4 + k, column * 4 + k);
+            }
 
-        Vec4 {
-            x: v[0],
-            y: v[1],
-            z: v[2],
-            w: v[3]
+            let scale = 1.0 / matrix[(column, column)];
+            for k in 0..4 {
+                matrix[(column, k)] *= scale;
+                inverse[(column, k)] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+
+                let factor = matrix[(row, column)];
+                for k in 0..4 {
+                    matrix[(row, k)] -= factor * matrix[(column, k)];
+                    inverse[(row, k)] -= factor * inverse[(column, k)];
+                }
+            }
         }
+
+        Some(inverse)
+    }
+}
+
+impl_matrix_vector_mul!(Matrix4, Vec4);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_matrices() {
+        let m = Matrix2::new([[1.0, 2.0], [3.0, 4.0]]);
+
+        assert!(m == Matrix2::new([[1.0, 2.0], [3.0, 4.0]]));
+        assert!(m != Matrix2::new([[1.0, 2.0], [3.0, 4.001]]));
+        assert!(m.approx_eq(&Matrix2::new([[1.0, 2.0], [3.0, 4.001]]), 0.01));
+        assert!(!m.approx_eq(&Matrix2::new([[1.1, 2.0], [3.0, 4.0]]), 0.01));
+    }
+
+    #[test]
+    fn displays_rows_with_aligned_columns() {
+        assert_eq!(
+            format!("{}", Matrix3::IDENTITY),
+            "[ 1.000 0.000 0.000 ]\n[ 0.000 1.000 0.000 ]\n[ 0.000 0.000 1.000 ]"
+        );
+        assert_eq!(
+            format!("{:.1}", Matrix2::new([[-10.0, 2.0], [3.0, 4.0]])),
+            "[ -10.0   2.0 ]\n[   3.0   4.0 ]"
+        );
+        assert_eq!(format!("{:?}", Matrix2::IDENTITY), "Matrix2 [[1.0, 0.0], [0.0, 1.0]]");
     }
 }
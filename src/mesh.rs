@@ -1,7 +1,10 @@
 use crate::error::Error;
+use crate::matrix::Matrix4;
+use crate::primitive::Color;
 use crate::vector::{
     Vec2,
-    Vec3
+    Vec3,
+    cross
 };
 
 pub struct Face {
@@ -10,36 +13,93 @@ pub struct Face {
     pub normals: [usize; 3]
 }
 
+// Face with its vertex attributes already looked up
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    pub texture_coords: [Vec2; 3],
+    pub normals: [Vec3; 3],
+    pub colors: Option<[Color; 3]>
+}
+
+// Edge between two vertices, the smaller index comes first.
+// Border edges have only one face
+pub struct Edge {
+    pub vertices: [usize; 2],
+    pub faces: (usize, Option<usize>)
+}
+
+pub struct Adjacency {
+    pub edges: Vec<Edge>
+}
+
 pub struct Mesh {
     vertices: Vec<Vec3>,
     texture_coords: Vec<Vec2>,
     faces: Vec<Face>,
-    normals: Vec<Vec3>
+    normals: Vec<Vec3>,
+    // Empty if the mesh has no vertex colors, otherwise one for every vertex
+    colors: Vec<Color>,
+    // Empty if ambient occlusion was not baked, otherwise one value for every vertex,
+    // from 0 for a fully occluded vertex to 1 for an exposed one
+    ambient_occlusion: Vec<f32>,
+    // File named by the mtllib statement, relative to the OBJ file
+    material_library: Option<String>
 }
 
 impl Mesh {
-    fn parse_obj(
-        buf_reader: std::io::BufReader<std::fs::File>,
-        vertices: &mut Vec<Vec3>,
-        faces: &mut Vec<Face>,
-        texture_coords: &mut Vec<Vec2>,
-        normals: &mut Vec<Vec3>
-    ) -> Result<(), Error> {
-        use std::io::BufRead;
+    // Lines parsed between the calls of a progress callback
+    const PROGRESS_INTERVAL: usize = 10000;
+    // Distance up to which baked ambient occlusion finds occluders, relative to the bounding box diagonal
+    const AO_DISTANCE: f32 = 0.2;
+    // Offset of the rays from the surface, relative to the distance
+    const AO_BIAS: f32 = 0.001;
+
+    fn parse_obj<R: std::io::BufRead>(&mut self, buf_reader: R, progress: &mut dyn FnMut(usize)) -> Result<(), Error> {
+        let mut lines_parsed = 0;
+
         for line in buf_reader.lines() {
             let line = line?;
 
-            let line: Vec<&str> = line.split(' ').collect();
+            lines_parsed += 1;
+            if lines_parsed % Self::PROGRESS_INTERVAL == 0 {
+                progress(lines_parsed);
+            }
+
+            // Also drops the '\r' left by CRLF line endings
+            let line: Vec<&str> = line.split_whitespace().collect();
+            if line.is_empty() {
+                continue;
+            }
 
             match line[0] {
-                "f" => faces.push(Self::parse_f(&line)?),
-                "v" => vertices.push(Self::parse_v(&line)?),
-                "vt" => texture_coords.push(Self::parse_vt(&line)?),
-                "vn" => normals.push(Self::parse_vn(&line)?),
+                "f" => self.faces.push(Self::parse_f(&line)?),
+                "v" => {
+                    let (vertex, color) = Self::parse_v(&line)?;
+
+                    // Vertices without a color listed before the first colored one are white
+                    if let Some(color) = color {
+                        self.colors.resize(self.vertices.len(), Color::WHITE);
+                        self.colors.push(color);
+                    }
+
+                    self.vertices.push(vertex);
+                },
+                "vt" => self.texture_coords.push(Self::parse_vt(&line)?),
+                "vn" => self.normals.push(Self::parse_vn(&line)?),
+                // Only the first material library is used
+                "mtllib" if line.len() > 1 && self.material_library.is_none() => {
+                    self.material_library = Some(line[1..].join(" "));
+                },
                 _ => {}
             }
         }
 
+        if !self.colors.is_empty() {
+            self.colors.resize(self.vertices.len(), Color::WHITE);
+        }
+
+        progress(lines_parsed);
+
         Ok(())
     }
 
@@ -63,43 +123,484 @@ impl Mesh {
         })
     }
 
-    fn parse_v(line: &[&str]) -> Result<Vec3, Error> {
-        Ok(Vec3 {
+    // Vertices have 2 to 4 components, z defaults to 0 and w to 1.
+    // Some exporters write x y z r g b instead, with the color channels ranging from 0 to 1
+    fn parse_v(line: &[&str]) -> Result<(Vec3, Option<Color>), Error> {
+        // x y [z [w]] or x y z r g b
+        if !(3..=5).contains(&line.len()) && line.len() != 7 {
+            return Err(Error::Parse);
+        }
+
+        if line.len() == 7 {
+            let channel = |c: &str| -> Result<u8, Error> {
+                Ok((c.parse::<f32>()?.clamp(0.0, 1.0) * 255.0).round() as u8)
+            };
+
+            let vertex = Vec3 {
+                x: line[1].parse::<f32>()?,
+                y: line[2].parse::<f32>()?,
+                z: line[3].parse::<f32>()?
+            };
+
+            let color = Color {
+                r: channel(line[4])?,
+                g: channel(line[5])?,
+                b: channel(line[6])?
+            };
+
+            return Ok((vertex, Some(color)));
+        }
+
+        let z = match line.get(3) {
+            Some(z) => z.parse::<f32>()?,
+            None => 0.0
+        };
+
+        let w = match line.get(4) {
+            Some(w) => w.parse::<f32>()?,
+            None => 1.0
+        };
+
+        // Points at infinity cannot be drawn
+        if w == 0.0 {
+            return Err(Error::Parse);
+        }
+
+        let vertex = Vec3 {
             x: line[1].parse::<f32>()?,
             y: line[2].parse::<f32>()?,
-            z: line[3].parse::<f32>()?
-        })
+            z
+        } * (1.0 / w);
+
+        Ok((vertex, None))
     }
 
+    // Texture coordinates have 1 to 3 components, v defaults to 0 and w is ignored
     fn parse_vt(line: &[&str]) -> Result<Vec2, Error> {
+        if line.len() < 2 {
+            return Err(Error::Parse);
+        }
+
+        let v = match line.get(2) {
+            Some(v) => v.parse::<f32>()?,
+            None => 0.0
+        };
+
         Ok(Vec2 {
-            x: line[2].parse::<f32>()?,
-            y: line[3].parse::<f32>()?
+            x: line[1].parse::<f32>()?,
+            y: v
         })
     }
 
     fn parse_vn(line: &[&str]) -> Result<Vec3, Error> {
+        if line.len() < 4 {
+            return Err(Error::Parse);
+        }
+
         Ok(Vec3 {
-            x: line[2].parse::<f32>()?,
-            y: line[3].parse::<f32>()?,
-            z: line[4].parse::<f32>()?
+            x: line[1].parse::<f32>()?,
+            y: line[2].parse::<f32>()?,
+            z: line[3].parse::<f32>()?
         })
     }
 
     pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
-        let mut vertices = Vec::<Vec3>::new();
-        let mut faces = Vec::<Face>::new();
-        let mut texture_coords = Vec::<Vec2>::new();
-        let mut normals = Vec::<Vec3>::new();
+        let file = std::fs::File::open(path)?;
+        Self::from_reader(std::io::BufReader::new(file))
+    }
 
+    // Calls the callback with the number of lines parsed so far and the number of all lines,
+    // every few thousand lines and once the whole file is parsed
+    pub fn from_file_with_progress<P: AsRef<std::path::Path>, F: FnMut(usize, Option<usize>)>(
+        path: P,
+        mut callback: F
+    ) -> Result<Self, Error> {
+        let total = Self::count_lines(std::fs::File::open(&path)?)?;
         let file = std::fs::File::open(path)?;
-        let buf_reader = std::io::BufReader::new(file);
 
-        Self::parse_obj(buf_reader, &mut vertices, &mut faces, &mut texture_coords, &mut normals)?;
+        Self::parse_reader(std::io::BufReader::new(file), &mut |lines| callback(lines, Some(total)))
+    }
 
-        Ok(Mesh {
-            vertices, faces, texture_coords, normals
-        })
+    fn count_lines<R: std::io::Read>(mut reader: R) -> Result<usize, Error> {
+        let mut buffer = [0u8; 1 << 16];
+        let mut lines = 0;
+        let mut last_byte = b'\n';
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            lines += buffer[..read].iter().filter(|&&byte| byte == b'\n').count();
+            last_byte = buffer[read - 1];
+        }
+
+        // The last line does not have to end with a newline
+        if last_byte != b'\n' {
+            lines += 1;
+        }
+
+        Ok(lines)
+    }
+
+    // Parses an in-memory OBJ file, for example one included with include_str!
+    pub fn from_obj_str(obj: &str) -> Result<Self, Error> {
+        Self::from_reader(obj.as_bytes())
+    }
+
+    pub fn from_reader<R: std::io::BufRead>(reader: R) -> Result<Self, Error> {
+        Self::parse_reader(reader, &mut |_| {})
+    }
+
+    fn parse_reader<R: std::io::BufRead>(reader: R, progress: &mut dyn FnMut(usize)) -> Result<Self, Error> {
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            faces: Vec::new(),
+            texture_coords: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            ambient_occlusion: Vec::new(),
+            material_library: None
+        };
+
+        mesh.parse_obj(reader, progress)?;
+
+        Ok(mesh)
+    }
+
+    pub fn material_library(&self) -> Option<&str> {
+        self.material_library.as_deref()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn normal_count(&self) -> usize {
+        self.normals.len()
+    }
+
+    pub fn texcoord_count(&self) -> usize {
+        self.texture_coords.len()
+    }
+
+    pub fn bounding_box(&self) -> (Vec3, Vec3) {
+        let mut min = Vec3 { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY };
+        let mut max = Vec3 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY };
+
+        for vertex in &self.vertices {
+            for i in 0..3 {
+                min[i] = min[i].min(vertex[i]);
+                max[i] = max[i].max(vertex[i]);
+            }
+        }
+
+        (min, max)
+    }
+
+    // Moves the center of the bounding box to the origin and scales the mesh uniformly,
+    // so that its longest axis spans from -1 to 1
+    pub fn center_and_normalize(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        let (min, max) = self.bounding_box();
+        let center = 0.5 * (min + max);
+        let extent = max - min;
+        let longest_axis = extent.x.max(extent.y).max(extent.z);
+        let scale = if longest_axis > 0.0 { 2.0 / longest_axis } else { 1.0 };
+
+        for vertex in self.vertices.iter_mut() {
+            *vertex = (*vertex - center) * scale;
+        }
+    }
+
+    // Normal vectors are transformed with the same matrix,
+    // which keeps them perpendicular only for rotations and uniform scaling
+    pub fn transform(&mut self, matrix: &Matrix4) {
+        for vertex in self.vertices.iter_mut() {
+            *vertex = (*matrix * vertex.homo_point()).point_proj();
+        }
+
+        for normal in self.normals.iter_mut() {
+            *normal = (*matrix * normal.homo_vector()).vector_proj().normalized();
+        }
+    }
+
+    // Merges vertices closer than epsilon into the first one of them, rewrites the faces
+    // and drops the ones which become degenerate.
+    // Normal vectors and texture coordinates are indexed separately and stay untouched
+    pub fn weld(&mut self, epsilon: f32) {
+        // Vertices are bucketed into cells of the grid,
+        // so that only the neighbouring cells have to be searched
+        let cell_size = epsilon.max(f32::EPSILON);
+        let cell = |vertex: &Vec3| (
+            (vertex.x / cell_size).floor() as i64,
+            (vertex.y / cell_size).floor() as i64,
+            (vertex.z / cell_size).floor() as i64
+        );
+
+        let mut grid = std::collections::HashMap::<(i64, i64, i64), Vec<usize>>::new();
+        let mut vertices = Vec::<Vec3>::new();
+        let mut colors = Vec::<Color>::new();
+        let mut ambient_occlusion = Vec::<f32>::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let (x, y, z) = cell(vertex);
+            let mut welded = None;
+
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        for &candidate in grid.get(&(x + dx, y + dy, z + dz)).into_iter().flatten() {
+                            if (vertices[candidate] - *vertex).len() <= epsilon {
+                                welded = Some(candidate);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+
+            let index = match welded {
+                Some(index) => index,
+                None => {
+                    vertices.push(*vertex);
+                    if self.has_vertex_colors() {
+                        colors.push(self.colors[i]);
+                    }
+                    if self.has_vertex_ao() {
+                        ambient_occlusion.push(self.ambient_occlusion[i]);
+                    }
+
+                    grid.entry((x, y, z)).or_default().push(vertices.len() - 1);
+                    vertices.len() - 1
+                }
+            };
+
+            remap.push(index);
+        }
+
+        for face in self.faces.iter_mut() {
+            for vertex in face.vertices.iter_mut() {
+                *vertex = remap[*vertex];
+            }
+        }
+
+        // Faces with welded vertices collapsed into a line or a point
+        self.faces.retain(|face| {
+            face.vertices[0] != face.vertices[1] &&
+            face.vertices[1] != face.vertices[2] &&
+            face.vertices[0] != face.vertices[2]
+        });
+
+        self.vertices = vertices;
+        self.colors = colors;
+        self.ambient_occlusion = ambient_occlusion;
+    }
+
+    // Sums of the normal vectors of the faces around every vertex, weighted by the areas of the faces
+    fn area_weighted_normals(&self) -> Vec<Vec3> {
+        let mut vertex_normals = vec![Vec3::ZERO; self.vertices.len()];
+
+        for face in &self.faces {
+            let p1 = self.vertices[face.vertices[0]];
+            let p2 = self.vertices[face.vertices[1]];
+            let p3 = self.vertices[face.vertices[2]];
+            let normal = cross(&(p2 - p1), &(p3 - p1));
+
+            for &vertex in &face.vertices {
+                vertex_normals[vertex] = vertex_normals[vertex] + normal;
+            }
+        }
+
+        vertex_normals
+    }
+
+    // Replaces the normal vectors with one smooth normal vector for every vertex
+    pub fn recompute_normals(&mut self) {
+        self.normals = self.area_weighted_normals().iter()
+            .map(|normal| if normal.len() > 0.0 { normal.normalized() } else { *normal })
+            .collect();
+
+        for face in self.faces.iter_mut() {
+            face.normals = face.vertices;
+        }
+    }
+
+    // Moves every vertex by lambda of the way towards the centroid of its neighbours in each iteration,
+    // which removes noise but also shrinks the mesh. Vertices are connected only through the faces,
+    // so vertices of a mesh with split seams should be welded first
+    pub fn smooth_laplacian(&mut self, iterations: usize, lambda: f32) {
+        let mut neighbours = vec![Vec::<usize>::new(); self.vertices.len()];
+        for edge in self.build_adjacency().edges {
+            let [a, b] = edge.vertices;
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+        }
+
+        for _ in 0..iterations {
+            self.vertices = self.vertices.iter().zip(neighbours.iter()).map(|(vertex, neighbours)| {
+                if neighbours.is_empty() {
+                    return *vertex;
+                }
+
+                let sum = neighbours.iter().fold(Vec3::ZERO, |sum, &neighbour| sum + self.vertices[neighbour]);
+                let centroid = sum * (1.0 / neighbours.len() as f32);
+
+                *vertex + lambda * (centroid - *vertex)
+            }).collect();
+        }
+
+        self.recompute_normals();
+    }
+
+    // Casts the given number of rays from every vertex over the hemisphere around its normal vector
+    // and stores the fraction of them which do not hit the mesh, weighted by the cosine of their angle.
+    // The renderer interpolates the baked values instead of computing ambient occlusion every frame,
+    // which is valid as long as the mesh is only moved or scaled uniformly
+    pub fn bake_vertex_ao(&mut self, samples: usize) {
+        let (min, max) = self.bounding_box();
+        let max_distance = (max - min).len() * Self::AO_DISTANCE;
+
+        let vertex_normals = self.area_weighted_normals();
+        let directions = Self::hemisphere_directions(samples);
+
+        self.ambient_occlusion = (0..self.vertices.len()).map(|vertex| {
+            if vertex_normals[vertex].len() == 0.0 || directions.is_empty() {
+                return 1.0;
+            }
+
+            let normal = vertex_normals[vertex].normalized();
+            // Moved off the surface, so that the faces around the vertex are not hit at the origin of the rays
+            let origin = self.vertices[vertex] + normal * (max_distance * Self::AO_BIAS);
+            // Any two vectors perpendicular to the normal vector and to each other
+            let helper = if normal.x.abs() < 0.9 {
+                Vec3 { x: 1.0, y: 0.0, z: 0.0 }
+            }
+            else {
+                Vec3 { x: 0.0, y: 1.0, z: 0.0 }
+            };
+            let tangent = cross(&helper, &normal).normalized();
+            let bitangent = cross(&normal, &tangent);
+
+            let unoccluded = directions.iter().filter(|direction| {
+                let ray = direction.x * tangent + direction.y * bitangent + direction.z * normal;
+
+                !self.faces.iter().any(|face| Self::ray_hits_triangle(
+                    &origin, &ray,
+                    &self.vertices[face.vertices[0]],
+                    &self.vertices[face.vertices[1]],
+                    &self.vertices[face.vertices[2]],
+                    max_distance
+                ))
+            }).count();
+
+            unoccluded as f32 / directions.len() as f32
+        }).collect();
+    }
+
+    // Directions around the z axis, spread evenly on a spiral and denser near the axis,
+    // so that every one of them has the same weight in cosine weighted sampling
+    fn hemisphere_directions(samples: usize) -> Vec<Vec3> {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+
+        (0..samples).map(|k| {
+            let radius = ((k as f32 + 0.5) / samples as f32).sqrt();
+            let angle = k as f32 * golden_angle;
+
+            Vec3 {
+                x: radius * angle.cos(),
+                y: radius * angle.sin(),
+                z: (1.0 - radius * radius).sqrt()
+            }
+        }).collect()
+    }
+
+    // Möller-Trumbore intersection, hits farther than max_distance along the normalized direction are ignored
+    fn ray_hits_triangle(origin: &Vec3, direction: &Vec3, p1: &Vec3, p2: &Vec3, p3: &Vec3, max_distance: f32) -> bool {
+        let edge1 = *p2 - *p1;
+        let edge2 = *p3 - *p1;
+        let p = cross(direction, &edge2);
+        let determinant = edge1 * p;
+
+        // The ray is parallel to the plane of the triangle
+        if determinant.abs() < f32::EPSILON {
+            return false;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let t = *origin - *p1;
+        let u = (t * p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return false;
+        }
+
+        let q = cross(&t, &edge1);
+        let v = (*direction * q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return false;
+        }
+
+        let distance = (edge2 * q) * inverse_determinant;
+        distance > 0.0 && distance <= max_distance
+    }
+
+    // Faces beyond the second one sharing an edge are ignored,
+    // so non-manifold edges get arbitrary neighbours
+    pub fn build_adjacency(&self) -> Adjacency {
+        let mut indices = std::collections::HashMap::<[usize; 2], usize>::new();
+        let mut edges = Vec::<Edge>::new();
+
+        for (face_index, face) in self.faces.iter().enumerate() {
+            for k in 0..3 {
+                let a = face.vertices[k];
+                let b = face.vertices[(k + 1) % 3];
+                let vertices = [a.min(b), a.max(b)];
+
+                match indices.get(&vertices) {
+                    Some(&edge) => {
+                        let faces = &mut edges[edge].faces;
+                        if faces.1.is_none() && faces.0 != face_index {
+                            faces.1 = Some(face_index);
+                        }
+                    },
+                    None => {
+                        indices.insert(vertices, edges.len());
+                        edges.push(Edge { vertices, faces: (face_index, None) });
+                    }
+                }
+            }
+        }
+
+        Adjacency { edges }
+    }
+
+    // Normal vector of the plane of a face, pointing towards which its vertices are counterclockwise
+    pub fn face_normal(&self, face: &Face) -> Vec3 {
+        let p1 = self.vertex(face.vertices[0]);
+        let p2 = self.vertex(face.vertices[1]);
+        let p3 = self.vertex(face.vertices[2]);
+
+        cross(&(*p2 - *p1), &(*p3 - *p1)).normalized()
+    }
+
+    // Edges between a face facing the viewer and one facing away, for a viewer looking along view_dir.
+    // Faces seen edge-on count as facing away
+    pub fn silhouette_edges(&self, view_dir: &Vec3) -> Vec<[usize; 2]> {
+        let facing = |face: usize| self.face_normal(&self.faces[face]) * *view_dir < 0.0;
+
+        self.build_adjacency().edges.iter().filter_map(|edge| match edge.faces {
+            (first, Some(second)) if facing(first) != facing(second) => Some(edge.vertices),
+            _ => None
+        }).collect()
     }
 
     #[inline(always)]
@@ -107,6 +608,24 @@ impl Mesh {
         &self.vertices[num]
     }
 
+    pub fn has_vertex_colors(&self) -> bool {
+        !self.colors.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn vertex_color(&self, num: usize) -> &Color {
+        &self.colors[num]
+    }
+
+    pub fn has_vertex_ao(&self) -> bool {
+        !self.ambient_occlusion.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn vertex_ao(&self, num: usize) -> f32 {
+        self.ambient_occlusion[num]
+    }
+
     #[inline(always)]
     pub fn texture_coord(&self, num: usize) -> &Vec2 {
         &self.texture_coords[num]
@@ -118,7 +637,296 @@ impl Mesh {
     }
 
     #[inline(always)]
-    pub fn faces(&self) -> std::slice::Iter<Face> {
+    pub fn faces(&self) -> std::slice::Iter<'_, Face> {
         self.faces.iter()
     }
+
+    pub fn triangles(&self) -> impl Iterator<Item = Triangle> + '_ {
+        self.faces().map(move |face| Triangle {
+            vertices: [
+                *self.vertex(face.vertices[0]),
+                *self.vertex(face.vertices[1]),
+                *self.vertex(face.vertices[2])
+            ],
+            texture_coords: [
+                *self.texture_coord(face.texture_coords[0]),
+                *self.texture_coord(face.texture_coords[1]),
+                *self.texture_coord(face.texture_coords[2])
+            ],
+            normals: [
+                *self.normal(face.normals[0]),
+                *self.normal(face.normals[1]),
+                *self.normal(face.normals[2])
+            ],
+            colors: if self.has_vertex_colors() {
+                Some([
+                    *self.vertex_color(face.vertices[0]),
+                    *self.vertex_color(face.vertices[1]),
+                    *self.vertex_color(face.vertices[2])
+                ])
+            }
+            else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centers_off_center_mesh() {
+        let mut mesh = Mesh::from_obj_str("\
+            v 10 20 30\nv 14 20 30\nv 10 22 31\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\n").unwrap();
+        mesh.center_and_normalize();
+
+        let (min, max) = mesh.bounding_box();
+        assert!(min.approx_eq(&Vec3 { x: -1.0, y: -0.5, z: -0.25 }, 1e-5));
+        assert!(max.approx_eq(&Vec3 { x: 1.0, y: 0.5, z: 0.25 }, 1e-5));
+    }
+
+    #[test]
+    fn counts_head_model_elements() {
+        let mesh = Mesh::from_file("head.obj").unwrap();
+
+        assert_eq!(mesh.vertex_count(), 1258);
+        assert_eq!(mesh.texcoord_count(), 1339);
+        assert_eq!(mesh.normal_count(), 1258);
+        assert_eq!(mesh.face_count(), 2492);
+    }
+
+    #[test]
+    fn reports_loading_progress() {
+        let mut calls = Vec::new();
+        let mesh = Mesh::from_file_with_progress("head.obj", |lines, total| {
+            calls.push((lines, total));
+        }).unwrap();
+
+        assert!(!calls.is_empty());
+        let (lines, total) = calls[calls.len() - 1];
+        assert_eq!(Some(lines), total);
+        assert!(lines >= mesh.vertex_count() + mesh.face_count());
+
+        // Long files report every few thousand lines
+        let obj = "v 0 0 0\n".repeat(25000);
+        let mut calls = Vec::new();
+        Mesh::parse_reader(obj.as_bytes(), &mut |lines| calls.push(lines)).unwrap();
+        assert_eq!(calls, vec![10000, 20000, 25000]);
+    }
+
+    #[test]
+    fn yields_triangle_for_every_face() {
+        let mesh = Mesh::from_obj_str("\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+            vt 0 0\nvt 1 1\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/2/1\nf 1/1/1 3/2/1 4/2/1\n").unwrap();
+        let triangles: Vec<Triangle> = mesh.triangles().collect();
+
+        assert_eq!(triangles.len(), mesh.face_count());
+        for (triangle, face) in triangles.iter().zip(mesh.faces()) {
+            for i in 0..3 {
+                assert_eq!(triangle.vertices[i], *mesh.vertex(face.vertices[i]));
+                assert_eq!(triangle.texture_coords[i], *mesh.texture_coord(face.texture_coords[i]));
+            }
+        }
+        assert_eq!(triangles[1].vertices[2], Vec3 { x: 0.0, y: 1.0, z: 0.0 });
+        assert!(triangles[0].colors.is_none());
+    }
+
+    #[test]
+    fn reads_mesh_from_memory() {
+        let obj = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\n";
+        let mesh = Mesh::from_reader(&obj[..]).unwrap();
+
+        assert_eq!(mesh.vertex_count(), 3);
+        assert_eq!(mesh.face_count(), 1);
+        assert_eq!(*mesh.vertex(1), Vec3 { x: 1.0, y: 0.0, z: 0.0 });
+    }
+
+    #[test]
+    fn parses_obj_string_literal() {
+        let mesh = Mesh::from_obj_str(include_str!("../head.obj")).unwrap();
+        assert_eq!(mesh.vertex_count(), 1258);
+
+        let mesh = Mesh::from_obj_str("\
+            v 0 0 0\nv 0 1 0\nv 1 0 0\nvt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\n").unwrap();
+        assert_eq!(mesh.face_count(), 1);
+        assert!(matches!(Mesh::from_obj_str("v 0 0 x\n"), Err(Error::Parse)));
+    }
+
+    #[test]
+    fn parses_vertices_with_two_to_four_components() {
+        let (vertex, _) = Mesh::parse_v(&["v", "1", "2"]).unwrap();
+        assert_eq!(vertex, Vec3 { x: 1.0, y: 2.0, z: 0.0 });
+
+        let (vertex, _) = Mesh::parse_v(&["v", "1", "2", "3", "2"]).unwrap();
+        assert_eq!(vertex, Vec3 { x: 0.5, y: 1.0, z: 1.5 });
+    }
+
+    #[test]
+    fn parses_texture_coordinates_with_one_to_three_components() {
+        assert_eq!(Mesh::parse_vt(&["vt", "0.5"]).unwrap(), Vec2 { x: 0.5, y: 0.0 });
+        assert_eq!(Mesh::parse_vt(&["vt", "0.5", "0.25"]).unwrap(), Vec2 { x: 0.5, y: 0.25 });
+        assert_eq!(Mesh::parse_vt(&["vt", "0.5", "0.25", "0.0"]).unwrap(), Vec2 { x: 0.5, y: 0.25 });
+    }
+
+    #[test]
+    fn parses_normal_vector() {
+        let mesh = Mesh::from_obj_str("vn 0.25 -0.5 0.75\n").unwrap();
+        assert_eq!(*mesh.normal(0), Vec3 { x: 0.25, y: -0.5, z: 0.75 });
+    }
+
+    #[test]
+    fn parses_crlf_line_endings() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\n";
+        let unix = Mesh::from_obj_str(obj).unwrap();
+        let windows = Mesh::from_obj_str(&obj.replace('\n', "\r\n")).unwrap();
+
+        assert_eq!(windows.vertices, unix.vertices);
+        assert_eq!(windows.texture_coords, unix.texture_coords);
+        assert_eq!(windows.normals, unix.normals);
+        assert_eq!(windows.faces[0].vertices, unix.faces[0].vertices);
+        assert_eq!(windows.faces[0].normals, unix.faces[0].normals);
+    }
+
+    #[test]
+    fn welds_coincident_vertices() {
+        // Two triangles of a quad, each with its own copy of the shared edge
+        let mut mesh = Mesh::from_obj_str("\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 0 0\nv 1 1 0\nv 0 1 0\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\nf 4/1/1 5/1/1 6/1/1\n").unwrap();
+        mesh.weld(1e-4);
+
+        assert_eq!(mesh.vertex_count(), 4);
+        let faces: Vec<[usize; 3]> = mesh.faces().map(|face| face.vertices).collect();
+        assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn weld_drops_collapsed_faces() {
+        let mut mesh = Mesh::from_obj_str("\
+            v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 0.00001 0\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\nf 1/1/1 2/1/1 4/1/1\n").unwrap();
+        mesh.weld(1e-3);
+
+        assert_eq!(mesh.vertex_count(), 3);
+        assert_eq!(mesh.face_count(), 1);
+    }
+
+    #[test]
+    fn face_on_cube_has_four_silhouette_edges() {
+        let mesh = Mesh::from_obj_str("\
+            v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+            v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\nf 1/1/1 3/1/1 4/1/1\n\
+            f 6/1/1 5/1/1 8/1/1\nf 6/1/1 8/1/1 7/1/1\n\
+            f 2/1/1 6/1/1 7/1/1\nf 2/1/1 7/1/1 3/1/1\n\
+            f 5/1/1 1/1/1 4/1/1\nf 5/1/1 4/1/1 8/1/1\n\
+            f 4/1/1 3/1/1 7/1/1\nf 4/1/1 7/1/1 8/1/1\n\
+            f 5/1/1 6/1/1 2/1/1\nf 5/1/1 2/1/1 1/1/1\n").unwrap();
+        assert_eq!(mesh.build_adjacency().edges.len(), 18);
+
+        // Looking at the z = 1 face, whose border is the outline of the cube
+        let mut silhouette = mesh.silhouette_edges(&Vec3 { x: 0.0, y: 0.0, z: -1.0 });
+        for edge in silhouette.iter_mut() {
+            edge.sort();
+        }
+        silhouette.sort();
+        assert_eq!(silhouette, vec![[0, 1], [0, 3], [1, 2], [2, 3]]);
+    }
+
+    #[test]
+    fn face_normal_points_towards_counterclockwise_side() {
+        let mesh = Mesh::from_obj_str("\
+            v 0 0 0\nv 2 0 0\nv 0 3 0\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\nf 1/1/1 3/1/1 2/1/1\n").unwrap();
+        let faces: Vec<&Face> = mesh.faces().collect();
+
+        assert!(mesh.face_normal(faces[0]).approx_eq(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }, 1e-6));
+        assert!(mesh.face_normal(faces[1]).approx_eq(&Vec3 { x: 0.0, y: 0.0, z: -1.0 }, 1e-6));
+    }
+
+    #[test]
+    fn crevice_vertex_gets_lower_baked_ao() {
+        // Two strips folded into a V along the z axis, open towards +y
+        let mut mesh = Mesh::from_obj_str("\
+            v 0 0 -1\nv 0 0 1\nv 1 1 -1\nv 1 1 1\nv -1 1 -1\nv -1 1 1\n\
+            vt 0 0\nvn 0 1 0\n\
+            f 1/1/1 2/1/1 4/1/1\nf 1/1/1 4/1/1 3/1/1\n\
+            f 1/1/1 6/1/1 2/1/1\nf 1/1/1 5/1/1 6/1/1\n").unwrap();
+        mesh.bake_vertex_ao(64);
+
+        let crevice = mesh.vertex_ao(0);
+        let exposed = mesh.vertex_ao(2);
+        assert!(exposed > 0.99);
+        assert!(crevice < exposed - 0.1);
+    }
+
+    #[test]
+    fn laplacian_smoothing_flattens_spike() {
+        // 3x3 grid with the middle vertex raised
+        let mut obj = String::new();
+        for y in 0..3 {
+            for x in 0..3 {
+                let z = if (x, y) == (1, 1) { 1.0 } else { 0.0 };
+                obj += &format!("v {} {} {}\n", x, y, z);
+            }
+        }
+        obj += "vt 0 0\nvn 0 0 1\n";
+        for y in 0..2 {
+            for x in 0..2 {
+                let corner = y * 3 + x + 1;
+                obj += &format!("f {0}/1/1 {1}/1/1 {2}/1/1\nf {0}/1/1 {2}/1/1 {3}/1/1\n",
+                    corner, corner + 1, corner + 4, corner + 3);
+            }
+        }
+        let mut mesh = Mesh::from_obj_str(&obj).unwrap();
+
+        // Largest distance of a vertex from the centroid of its neighbours
+        let max_displacement = |mesh: &Mesh| {
+            let mut neighbours = vec![Vec::new(); mesh.vertex_count()];
+            for edge in mesh.build_adjacency().edges {
+                let [a, b] = edge.vertices;
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+
+            neighbours.iter().enumerate().map(|(vertex, neighbours)| {
+                let sum = neighbours.iter().fold(Vec3::ZERO, |sum, &neighbour| sum + *mesh.vertex(neighbour));
+                (sum * (1.0 / neighbours.len() as f32) - *mesh.vertex(vertex)).len()
+            }).fold(0.0, f32::max)
+        };
+
+        let before = max_displacement(&mesh);
+        mesh.smooth_laplacian(3, 0.5);
+        let after = max_displacement(&mesh);
+
+        assert!(after < before * 0.5);
+        assert!(mesh.vertex(4).z < 0.5);
+    }
+
+    #[test]
+    fn rejects_vertex_at_infinity() {
+        assert!(matches!(Mesh::from_obj_str("v 1 2 3 0\n"), Err(Error::Parse)));
+
+        let mesh = Mesh::from_obj_str("v 2 4 6 2\n").unwrap();
+        assert_eq!(*mesh.vertex(0), Vec3 { x: 1.0, y: 2.0, z: 3.0 });
+    }
+
+    #[test]
+    fn rejects_vertex_with_extra_components() {
+        assert!(matches!(Mesh::from_obj_str("v 1 2 3 1 0.5\n"), Err(Error::Parse)));
+        assert!(matches!(Mesh::from_obj_str("v 1 2 3 1 0 0 0\n"), Err(Error::Parse)));
+        assert!(matches!(Mesh::from_obj_str("v 1\n"), Err(Error::Parse)));
+        assert!(Mesh::from_obj_str("v 1 2\nv 1 2 3 1 0 0\n").is_ok());
+    }
 }
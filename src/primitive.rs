@@ -13,6 +13,95 @@ impl Color {
     }
 }
 
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+
+    // All channels set to the same level
+    pub const fn gray(level: u8) -> Color {
+        Color { r: level, g: level, b: level }
+    }
+
+    // Linear interpolation, t = 0 gives self and t = 1 gives other
+    pub fn mix(&self, other: &Color, t: f32) -> Color {
+        let mix_channel = |a: u8, b: u8| {
+            Self::color_f32_to_u8(f32::from(a) + (f32::from(b) - f32::from(a)) * t)
+        };
+
+        Color {
+            r: mix_channel(self.r, other.r),
+            g: mix_channel(self.g, other.g),
+            b: mix_channel(self.b, other.b)
+        }
+    }
+
+    fn srgb_to_linear(channel: u8) -> f32 {
+        let channel = f32::from(channel) / 255.0;
+        if channel <= 0.04045 {
+            channel / 12.92
+        }
+        else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn linear_to_srgb(channel: f32) -> u8 {
+        let channel = if channel <= 0.0031308 {
+            channel * 12.92
+        }
+        else {
+            1.055 * channel.powf(1.0 / 2.4) - 0.055
+        };
+
+        Self::color_f32_to_u8(channel * 255.0 + 0.5)
+    }
+
+    // Relative luminance from 0 to 1, computed with Rec. 709 weights on linear channels
+    pub fn luminance(&self) -> f32 {
+        0.2126 * Self::srgb_to_linear(self.r) +
+        0.7152 * Self::srgb_to_linear(self.g) +
+        0.0722 * Self::srgb_to_linear(self.b)
+    }
+
+    // Channels stop at 255 instead of wrapping around
+    pub fn saturating_add(&self, other: &Color) -> Color {
+        Color {
+            r: self.r.saturating_add(other.r),
+            g: self.g.saturating_add(other.g),
+            b: self.b.saturating_add(other.b)
+        }
+    }
+
+    // Applies the function to every channel converted to linear space
+    pub fn map_linear<F: Fn(f32) -> f32>(&self, function: F) -> Color {
+        Color {
+            r: Self::linear_to_srgb(function(Self::srgb_to_linear(self.r))),
+            g: Self::linear_to_srgb(function(Self::srgb_to_linear(self.g))),
+            b: Self::linear_to_srgb(function(Self::srgb_to_linear(self.b)))
+        }
+    }
+
+    // Brightness is added as a fraction of the full range,
+    // contrast scales the distance of the channels from mid-gray
+    pub fn adjusted(&self, brightness: f32, contrast: f32) -> Color {
+        let adjust_channel = |channel: u8| {
+            Self::color_f32_to_u8((f32::from(channel) - 127.5) * contrast + 127.5 + brightness * 255.0 + 0.5)
+        };
+
+        Color {
+            r: adjust_channel(self.r),
+            g: adjust_channel(self.g),
+            b: adjust_channel(self.b)
+        }
+    }
+
+    pub fn to_grayscale(self) -> Color {
+        let gray = Self::linear_to_srgb(self.luminance());
+        Color { r: gray, g: gray, b: gray }
+    }
+}
+
 impl std::ops::Mul<f32> for Color {
     type Output = Color;
 
@@ -29,7 +118,29 @@ impl std::ops::Mul<f32> for Color {
     }
 }
 
-#[allow(dead_code)]
+// Multiplies the channels as if they ranged from 0 to 1
+impl std::ops::Mul<Color> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Self::Output {
+        let mul_channel = |a: u8, b: u8| ((u16::from(a) * u16::from(b) + 127) / 255) as u8;
+
+        Color {
+            r: mul_channel(self.r, rhs.r),
+            g: mul_channel(self.g, rhs.g),
+            b: mul_channel(self.b, rhs.b)
+        }
+    }
+}
+
+impl std::ops::Add<Color> for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Color) -> Self::Output {
+        self.saturating_add(&rhs)
+    }
+}
+
 impl Color {
     pub const WHITE: Color = Color { r: 255, g: 255, b: 255 };
     pub const GRAY: Color = Color { r: 127, g: 127, b: 127 };
@@ -40,6 +151,9 @@ impl Color {
     pub const YELLOW: Color = Color { r: 255, g: 255, b: 0 };
     pub const MAGENTA: Color = Color { r: 255, g: 0, b: 255 };
     pub const CYAN: Color = Color { r: 0, g: 255, b: 255 };
+    pub const ORANGE: Color = Color { r: 255, g: 165, b: 0 };
+    pub const PURPLE: Color = Color { r: 128, g: 0, b: 128 };
+    pub const BROWN: Color = Color { r: 139, g: 69, b: 19 };
 }
 
 #[derive(Clone)]
@@ -47,3 +161,46 @@ pub struct Size {
     pub width: i32,
     pub height: i32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn green_is_brighter_than_blue() {
+        assert!(Color::GREEN.luminance() > Color::BLUE.luminance());
+        assert!(Color::rgb(0, 100, 0).luminance() > Color::rgb(0, 0, 100).luminance());
+
+        let gray = Color::GREEN.to_grayscale();
+        assert!(gray.r == gray.g && gray.g == gray.b);
+        assert!(gray.r > Color::BLUE.to_grayscale().r);
+    }
+
+    #[test]
+    fn saturating_add_stops_at_white() {
+        let sum = Color::rgb(200, 100, 255).saturating_add(&Color::rgb(100, 100, 1));
+        assert_eq!((sum.r, sum.g, sum.b), (255, 200, 255));
+    }
+
+    #[test]
+    fn gray_has_equal_channels() {
+        let gray = Color::gray(128);
+        assert_eq!((gray.r, gray.g, gray.b), (128, 128, 128));
+        let rgb = Color::rgb(1, 2, 3);
+        assert_eq!((rgb.r, rgb.g, rgb.b), (1, 2, 3));
+    }
+
+    #[test]
+    fn brightness_raises_and_contrast_spreads_channels() {
+        let color = Color::rgb(50, 120, 200);
+        let unchanged = color.adjusted(0.0, 1.0);
+        assert_eq!((unchanged.r, unchanged.g, unchanged.b), (50, 120, 200));
+
+        let brighter = color.adjusted(0.1, 1.0);
+        assert!(brighter.r > color.r && brighter.g > color.g && brighter.b > color.b);
+
+        // Channels below mid-gray get darker and the ones above it brighter
+        let contrasted = color.adjusted(0.0, 1.5);
+        assert!(contrasted.r < color.r && contrasted.g < color.g && contrasted.b > color.b);
+    }
+}
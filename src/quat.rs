@@ -0,0 +1,143 @@
+use crate::matrix::Matrix4;
+use crate::vector::Vec3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+impl Quaternion {
+    pub const IDENTITY: Self = Self { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    // Counterclockwise rotation when looking from the positive end of the axis
+    pub fn from_axis_angle(axis: &Vec3, angle: f32) -> Self {
+        let axis = axis.normalized();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+
+        Quaternion {
+            w: cos,
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin
+        }
+    }
+
+    pub fn len(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Self {
+        let len = self.len();
+        Quaternion {
+            w: self.w / len,
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len
+        }
+    }
+
+    fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    // Rotation matrix of a unit quaternion
+    pub fn to_matrix4(self) -> Matrix4 {
+        let Quaternion { w, x, y, z } = self;
+
+        Matrix4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    // Spherical linear interpolation along the shorter arc, t = 0 gives self and t = 1 gives other
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Self {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+
+        // q and -q represent the same rotation
+        if cos < 0.0 {
+            other = other * -1.0;
+            cos = -cos;
+        }
+
+        // Nearly parallel quaternions are interpolated linearly to avoid dividing by zero
+        if cos > 0.9995 {
+            return (*self * (1.0 - t) + other * t).normalized();
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+
+        (*self * (((1.0 - t) * angle).sin() / sin) + other * ((t * angle).sin() / sin)).normalized()
+    }
+}
+
+impl std::ops::Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    // Hamilton product, the result applies rhs first and then self
+    fn mul(self, rhs: Quaternion) -> Self::Output {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w
+        }
+    }
+}
+
+impl std::ops::Mul<f32> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Quaternion {
+            w: self.w * rhs,
+            x: self.x * rhs,
+            y: self.y * rhs,
+            z: self.z * rhs
+        }
+    }
+}
+
+impl std::ops::Add<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, rhs: Quaternion) -> Self::Output {
+        Quaternion {
+            w: self.w + rhs.w,
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform;
+
+    #[test]
+    fn rotation_matches_matrix() {
+        let angle = std::f32::consts::FRAC_PI_2;
+        let rotation = Quaternion::from_axis_angle(&Vec3 { x: 0.0, y: 1.0, z: 0.0 }, angle);
+
+        assert!(rotation.to_matrix4().approx_eq(&transform::rotate_y(angle), 1e-6));
+    }
+
+    #[test]
+    fn slerp_stays_normalized() {
+        let from = Quaternion::from_axis_angle(&Vec3 { x: 1.0, y: 0.0, z: 0.0 }, 0.3);
+        let to = Quaternion::from_axis_angle(&Vec3 { x: 0.0, y: 1.0, z: 1.0 }, 2.0);
+        let halfway = from.slerp(&to, 0.5);
+
+        assert!((halfway.len() - 1.0).abs() < 1e-6);
+        assert_eq!(from.slerp(&to, 0.0), from);
+    }
+}
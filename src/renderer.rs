@@ -1,7 +1,21 @@
-use crate::drawer::Drawer;
+use crate::drawer::{
+    Background,
+    Drawer,
+    Layer,
+    WinSize
+};
+use crate::camera::Camera;
 use crate::error::Error;
 use crate::mesh::Mesh;
 use crate::image::Image;
+use crate::light::Light;
+use crate::material::Material;
+use crate::shader::{
+    PhongShader,
+    Shader,
+    TriangleAttributes,
+    Varyings
+};
 use crate::transform;
 use crate::primitive::{
     Color,
@@ -10,7 +24,14 @@ use crate::primitive::{
 
 use crate::vector::{
     Vec2,
-    Vec3
+    Vec3,
+    Vec4,
+    cross
+};
+use std::ops::Range;
+use std::time::{
+    Duration,
+    Instant
 };
 
 use crate::matrix::{
@@ -21,15 +42,374 @@ use crate::matrix::{
 pub struct Renderer {
     drawer: Drawer,
     zbuffer: Vec<f32>,
+    depth_mode: DepthMode,
+    // Only fragments at the depth found by depth_prepass are shaded
+    depth_prepass: bool,
+    // Set while depth_prepass runs, rasterization stops after the depth test
+    depth_only: bool,
 
     view_matrix: Matrix4,
     projection_matrix: Matrix4,
+    // Convention of the world coordinates, including the camera and the lights
+    handedness: Handedness,
 
     shadow_buffer: Vec<f32>,
+    // Size of the shadow buffer, the same as the size of the drawing plane if not set
+    shadow_resolution: Option<Size>,
     shadow_view_matrix: Matrix4,
+    // Shadows are cast only by the first directional light
+    has_shadow_light: bool,
+    shadows: bool,
+    // Keeps the shadow buffer of the first frame drawn after it is invalidated
+    static_shadows: bool,
+    // Some geometry was drawn into the shadow buffer since it was cleared
+    shadow_map_filled: bool,
+    // The shadow buffer is complete and is not drawn into
+    shadow_map_cached: bool,
 
     normal_projection_matrix: Matrix4,
-    light_vector: Vec3
+    lights: Vec<Light>,
+
+    depth_range: Option<(f32, f32)>,
+    fog: Option<Fog>,
+    edge_outline: Option<EdgeOutline>,
+    shading_mode: ShadingMode,
+    specular_model: SpecularModel,
+
+    ao_quality: AoQuality,
+    // Downsampled z-buffer used for ambient occlusion, empty for full quality
+    ao_buffer: Vec<f32>,
+    // Distance in pixels up to which occluders are searched for, the whole buffer if not set
+    ao_radius: Option<i32>,
+
+    stats: RenderStats,
+
+    // Width to height ratio of a pixel
+    pixel_aspect: f32,
+
+    antialiasing: bool,
+    rasterizer: Rasterizer,
+    shadow_filter: ShadowFilter,
+    tonemap: ToneMap,
+    // Number of levels the diffuse light is quantized to, for cel shading
+    toon_bands: Option<u32>,
+    // Texture coordinates are used with v replaced by 1 - v
+    flip_v: bool,
+
+    // Frame rate limit adapting to the speed of the output, none if frames are displayed immediately
+    pacer: Option<FramePacer>,
+
+    // Background drawn by refresh
+    background: Background,
+    // Resizes to the terminal when refreshed, otherwise only resize changes the size
+    follows_terminal: bool
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // Light intensity multiplies the sRGB color, which is clamped
+    None,
+    // Lit linear color c is mapped to c / (1 + c)
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces
+}
+
+impl ToneMap {
+    fn map(&self, channel: f32) -> f32 {
+        match self {
+            ToneMap::None => channel.min(1.0),
+            ToneMap::Reinhard => channel / (1.0 + channel),
+            ToneMap::Aces => {
+                let numerator = channel * (2.51 * channel + 0.03);
+                let denominator = channel * (2.43 * channel + 0.59) + 0.14;
+                (numerator / denominator).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DepthMode {
+    // Depth stored as it is, greater values are closer to the camera
+    Standard,
+    // Depth stored as 1 - z, smaller values are closer to the camera
+    Reversed
+}
+
+impl DepthMode {
+    fn clear_value(&self) -> f32 {
+        match self {
+            DepthMode::Standard => f32::NEG_INFINITY,
+            DepthMode::Reversed => f32::INFINITY
+        }
+    }
+
+    fn encode(&self, depth: f32) -> f32 {
+        match self {
+            DepthMode::Standard => depth,
+            DepthMode::Reversed => 1.0 - depth
+        }
+    }
+
+    fn decode(&self, stored: f32) -> f32 {
+        // 1 - z is its own inverse
+        self.encode(stored)
+    }
+
+    fn is_nearer(&self, stored: f32, than: f32) -> bool {
+        match self {
+            DepthMode::Standard => stored > than,
+            DepthMode::Reversed => stored < than
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Handedness {
+    // The x axis points right, y up and z towards the viewer, like in OpenGL
+    Right,
+    // The z axis points away from the viewer, like in Direct3D
+    Left
+}
+
+impl Handedness {
+    // Applied to the view space. look_at assumes a right-handed world,
+    // so it shows a left-handed one mirrored horizontally, which negating x undoes
+    fn view_axes(&self) -> Matrix4 {
+        match self {
+            Handedness::Right => Matrix4::IDENTITY,
+            Handedness::Left => transform::scale(&Vec3 { x: -1.0, y: 1.0, z: 1.0 })
+        }
+    }
+
+    // Cross products of vectors in a left-handed world point the other way
+    fn cross(&self, v: &Vec3, u: &Vec3) -> Vec3 {
+        match self {
+            Handedness::Right => cross(v, u),
+            Handedness::Left => cross(u, v)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    // A single depth comparison
+    Hard,
+    // Percentage-closer filtering, averages the comparisons in a square of the given size
+    Pcf(i32)
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RenderStats {
+    pub triangles: usize,
+    // Triangles discarded before rasterization
+    pub triangles_culled: usize,
+    // Culled triangles which are completely outside the view frustum
+    pub triangles_frustum_culled: usize,
+    pub fragments: usize,
+    // Pixels which differ from the previous frame, counted when displayed
+    pub changed_pixels: usize
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShadingMode {
+    // Normal vectors interpolated between vertices and perturbed by the normal map
+    Smooth,
+    // A single geometric normal vector for the whole face
+    Flat,
+    // Light computed at the vertices and interpolated across the face
+    Gouraud
+}
+
+// Used by the depth prepass, which never shades fragments
+struct DepthOnlyShader;
+
+impl Shader for DepthOnlyShader {
+    fn fragment(&self, _bary: Vec3, _varyings: &Varyings) -> Option<Color> {
+        None
+    }
+}
+
+// Attributes of a single vertex of a triangle drawn on its own
+#[derive(Clone, Copy)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub uv: Vec2,
+    pub normal: Vec3
+}
+
+// Vertex in world space with its projections, computed once for vertices shared by many faces
+#[derive(Clone, Copy)]
+struct ProjectedVertex {
+    position: Vec3,
+    clip: Vec4,
+    shadow: Option<Vec3>
+}
+
+#[derive(Clone, Copy)]
+struct ClipVertex {
+    position: Vec3,
+    uv: Vec2,
+    // Already transformed, which commutes with interpolation
+    normal: Vec3,
+    color: Color,
+    ambient_occlusion: f32
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            position: self.position + t * (other.position - self.position),
+            uv: self.uv + t * (other.uv - self.uv),
+            normal: self.normal + t * (other.normal - self.normal),
+            color: self.color.mix(&other.color, t),
+            ambient_occlusion: self.ambient_occlusion + t * (other.ambient_occlusion - self.ambient_occlusion)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SpecularModel {
+    // Reflected light vector compared with the view direction
+    Phong,
+    // Normal vector compared with the vector halfway between the light and view directions
+    BlinnPhong
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Rasterizer {
+    // Edge functions of vertices snapped to a subpixel grid, evaluated incrementally with integers
+    FixedPoint,
+    // Barycentric coordinates computed separately for every pixel
+    Float
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AoQuality {
+    // Ambient occlusion marched through the z-buffer
+    Full,
+    // Marched through a half resolution z-buffer, which keeps the nearest depth of every 2x2 block
+    Half,
+    // Every fragment is fully lit by the ambient light
+    Off
+}
+
+impl AoQuality {
+    fn scale(&self) -> i32 {
+        match self {
+            AoQuality::Full | AoQuality::Off => 1,
+            AoQuality::Half => 2
+        }
+    }
+}
+
+// Depth buffer sampled by ambient occlusion rays
+struct AoBuffer<'a> {
+    depths: &'a [f32],
+    depth_mode: DepthMode,
+    size: Size,
+    scale: i32,
+    // In full resolution pixels
+    radius: Option<i32>
+}
+
+impl<'a> AoBuffer<'a> {
+    // Highest elevation of the depths seen from the pixel along the ray with the given step
+    fn horizon_angle(&self, x: i32, y: i32, depth: f32, x_step: i32, y_step: i32) -> f32 {
+        let mut zbuffer_x = x;
+        let mut zbuffer_y = y;
+        let mut max_angle = 0.0;
+
+        loop {
+            zbuffer_x += x_step;
+            zbuffer_y += y_step;
+
+            if zbuffer_x >= self.size.width ||
+               zbuffer_y >= self.size.height ||
+               zbuffer_x < 0 || zbuffer_y < 0 {
+                return max_angle;
+            }
+
+            // Distance measured in full resolution pixels
+            let length = (((x - zbuffer_x).pow(2) + (y - zbuffer_y).pow(2)) as f32).sqrt() *
+                self.scale as f32;
+
+            if let Some(radius) = self.radius {
+                if length > radius as f32 {
+                    return max_angle;
+                }
+            }
+
+            let zbuffer_ray_index = (zbuffer_y * self.size.width + zbuffer_x) as usize;
+
+            if self.depths[zbuffer_ray_index] == self.depth_mode.clear_value() {
+                continue;
+            }
+
+            let height = self.depth_mode.decode(self.depths[zbuffer_ray_index]) - depth;
+            let angle = (height / length).atan();
+
+            if angle > max_angle {
+                max_angle = angle;
+            }
+        }
+    }
+}
+
+struct Fog {
+    color: Color,
+    near: f32,
+    far: f32
+}
+
+struct EdgeOutline {
+    // Minimal depth difference between neighbouring pixels
+    threshold: f32,
+    color: Color
+}
+
+// Spaces out the frames, so that a terminal which receives them slower than they are rendered
+// has time to catch up instead of queueing more and more of them
+struct FramePacer {
+    // Minimal time between two displayed frames
+    budget: Duration,
+    last_frame: Option<Instant>
+}
+
+impl FramePacer {
+    // 60 frames per second, the cap used while the terminal keeps up
+    const MIN_BUDGET: Duration = Duration::from_micros(16_667);
+    // Fraction of the budget kept after every frame written out in time
+    const RECOVERY: f32 = 0.9;
+
+    fn new() -> Self {
+        FramePacer {
+            budget: Self::MIN_BUDGET,
+            last_frame: None
+        }
+    }
+
+    fn wait(&self) {
+        if let Some(last_frame) = self.last_frame {
+            let elapsed = last_frame.elapsed();
+            if elapsed < self.budget {
+                std::thread::sleep(self.budget - elapsed);
+            }
+        }
+    }
+
+    fn frame_displayed(&mut self, output_time: Duration) {
+        // The terminal gets as much idle time as it took to receive the frame
+        self.budget = if output_time > self.budget {
+            2 * output_time
+        }
+        else {
+            self.budget.mul_f32(Self::RECOVERY).max(Self::MIN_BUDGET)
+        };
+
+        self.last_frame = Some(Instant::now());
+    }
 }
 
 struct BoundingBox {
@@ -40,431 +420,3063 @@ struct BoundingBox {
 }
 
 impl Renderer {
-    fn create_zbuffer(plane_size: Size) -> Vec<f32> {
+    // Vertices with smaller w are behind or too close to the camera
+    const NEAR_PLANE_W: f32 = 0.01;
+    const COVERAGE_SAMPLES: usize = 4;
+    // Fixed point vertex coordinates are rounded to this fraction of a pixel
+    const SUBPIXEL_STEPS: i64 = 16;
+    const OUTLINE_WIDTH: i32 = 2;
+    // Outlines stay visible this much behind the z-buffer, as they lie on the edge of the surface
+    const OUTLINE_DEPTH_BIAS: f32 = 0.05;
+
+    fn create_zbuffer(plane_size: Size, depth_mode: DepthMode) -> Vec<f32> {
         let mut v = Vec::with_capacity((plane_size.width * plane_size.height) as usize);
         for _ in 0..v.capacity() {
-            v.push(std::f32::NEG_INFINITY);
+            v.push(depth_mode.clear_value());
         }
 
         v
     }
 
     pub fn new() -> Self {
-        let drawer = Drawer::new();
+        let mut renderer = Self::with_drawer(Drawer::new());
+        renderer.follows_terminal = true;
+        renderer
+    }
+
+    // Renders without a terminal, the frames can be saved with save_frame
+    pub fn with_size(win_size: WinSize) -> Self {
+        Self::with_drawer(Drawer::with_writer(std::io::sink(), win_size))
+    }
+
+    pub fn with_drawer(drawer: Drawer) -> Self {
         let light_vector = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
 
         Renderer {
-            zbuffer: Self::create_zbuffer(drawer.plane_size()),
+            zbuffer: Self::create_zbuffer(drawer.plane_size(), DepthMode::Standard),
+            depth_mode: DepthMode::Standard,
+            depth_prepass: false,
+            depth_only: false,
 
             projection_matrix: transform::perspective(3.0),
             view_matrix: Matrix4::IDENTITY,
+            handedness: Handedness::Right,
 
-            shadow_buffer: Self::create_zbuffer(drawer.plane_size()),
-            shadow_view_matrix: transform::look_at(
-                &light_vector, &Vec3::ZERO, &Vec3 { x: 0.0, y: 1.0, z: 0.0 }
-            ),
+            shadow_buffer: Self::create_zbuffer(drawer.plane_size(), DepthMode::Standard),
+            shadow_resolution: None,
+            shadow_view_matrix: Self::shadow_view_matrix(&light_vector),
+            has_shadow_light: true,
+            shadows: true,
+            static_shadows: false,
+            shadow_map_filled: false,
+            shadow_map_cached: false,
 
             normal_projection_matrix: transform::normal_perspective(3.0),
-            light_vector,
+            lights: vec![Light::Directional { dir: light_vector }],
+
+            depth_range: None,
+            fog: None,
+            edge_outline: None,
+            shading_mode: ShadingMode::Smooth,
+            specular_model: SpecularModel::Phong,
+
+            ao_quality: AoQuality::Full,
+            ao_buffer: Vec::new(),
+            ao_radius: None,
+
+            stats: RenderStats::default(),
+
+            pixel_aspect: drawer.pixel_aspect(),
+
+            antialiasing: false,
+            rasterizer: Rasterizer::FixedPoint,
+            shadow_filter: ShadowFilter::Hard,
+            tonemap: ToneMap::None,
+
+            toon_bands: None,
+            flip_v: false,
+            pacer: None,
+            background: Background::Solid(Color::BLACK),
+            follows_terminal: false,
 
             drawer
         }
     }
 
-    #[inline(always)]
-    fn to_drawer_coordinates(&self, vec: Vec2) -> (i32, i32) {
-        (
-            (self.drawer.plane_size().width as f32 * (vec.x + 1.0) / 2.0) as i32,
-            (self.drawer.plane_size().height as f32 * (-vec.y + 1.0) / 2.0) as i32
-        )
+    // Takes effect from the next frame, as the z-buffer is cleared with a different value
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+        self.zbuffer = Self::create_zbuffer(self.drawer.plane_size(), depth_mode);
     }
 
-    #[inline(always)]
-    fn to_renderer_coordinates(&self, x: i32, y: i32) -> Vec2 {
-        Vec2 {
-            x: x as f32 / self.drawer.plane_size().width as f32 * 2.0 - 1.0,
-            y: -y as f32 / self.drawer.plane_size().height as f32 * 2.0 + 1.0
-        }
+    // Makes the output more readable on dim terminals
+    pub fn set_display_adjust(&mut self, brightness: f32, contrast: f32) {
+        self.drawer.set_display_adjust(brightness, contrast);
     }
 
-    pub fn refresh(&mut self, color: &Color) {
-        let (rows, cols) =  unsafe {
-            let mut ws: libc::winsize = std::mem::MaybeUninit::uninit().assume_init();
-            libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws);
-            (i32::from(ws.ws_row), i32::from(ws.ws_col))
-        };
+    // Markers, outlines and text drawn on the overlay layer are not cleared by refresh
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.drawer.set_layer(layer);
+    }
+
+    pub fn clear_overlay(&mut self) {
+        self.drawer.clear_overlay();
+    }
+
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: &Color) {
+        self.drawer.draw_text(x, y, text, color);
+    }
+
+    // Textures are sampled with v = 0 at the bottom row, like OBJ files expect.
+    // TGA files with a top-left origin are loaded without flipping their rows,
+    // so they appear upside down unless v is flipped as well
+    pub fn set_flip_v(&mut self, flip_v: bool) {
+        self.flip_v = flip_v;
+    }
 
-        if rows * 2 != self.drawer.plane_size().height || cols != self.drawer.plane_size().width {
-            self.drawer = Drawer::new();
-            self.zbuffer = Self::create_zbuffer(self.drawer.plane_size());
+    // Flipped before interpolation, so that the tangent basis of normal maps follows the texture
+    fn oriented_uv(&self, uv: &Vec2) -> Vec2 {
+        if self.flip_v {
+            Vec2 { x: uv.x, y: 1.0 - uv.y }
         }
         else {
-            for p in self.zbuffer.iter_mut() {
-                *p = std::f32::NEG_INFINITY;
-            }
+            *uv
         }
+    }
 
-        self.drawer.clear(color);
+    // Lowers the frame rate when frames are written out slower than they are displayed,
+    // e.g. over a slow SSH connection, otherwise caps it at 60 frames per second
+    pub fn set_adaptive_pacing(&mut self, adaptive_pacing: bool) {
+        self.pacer = if adaptive_pacing { Some(FramePacer::new()) } else { None };
     }
 
-    pub fn display(&mut self) -> Result<(), Error> {
-        self.drawer.display()?;
-        Ok(())
+    pub fn set_rasterizer(&mut self, rasterizer: Rasterizer) {
+        self.rasterizer = rasterizer;
     }
 
-    fn bounding_box(&self, p1: &Vec3, p2: &Vec3, p3: &Vec3) -> BoundingBox {
-        let (bbox_min_x, bbox_min_y) = self.to_drawer_coordinates(Vec2 {
-            x: Self::min_bounding_box(p1.x, p2.x, p3.x),
-            y: Self::max_bounding_box(p1.y, p2.y, p3.y)
-        });
+    // Softens edges by blending partially covered pixels with the background
+    pub fn set_antialiasing(&mut self, antialiasing: bool) {
+        self.antialiasing = antialiasing;
+        self.drawer.set_antialiasing(antialiasing);
+    }
 
-        let (bbox_max_x, bbox_max_y) = self.to_drawer_coordinates(Vec2 {
-            x: Self::max_bounding_box(p1.x, p2.x, p3.x),
-            y: Self::min_bounding_box(p1.y, p2.y, p3.y)
-        });
+    pub fn set_pixel_aspect(&mut self, pixel_aspect: f32) {
+        self.pixel_aspect = pixel_aspect;
+    }
 
-        BoundingBox {
-            min_x: std::cmp::max(bbox_min_x, 0),
-            max_x: std::cmp::min(bbox_max_x, self.drawer.plane_size().width - 1),
-            min_y: std::cmp::max(bbox_min_y, 0),
-            max_y: std::cmp::min(bbox_max_y, self.drawer.plane_size().height - 1)
+    // Scale of the renderer coordinates, which makes the shorter side of the plane
+    // span from -1 to 1 and keeps the same unit length on both axes
+    #[inline(always)]
+    fn aspect_scale(&self) -> Vec2 {
+        let plane_size = self.drawer.plane_size();
+        let width = plane_size.width as f32 * self.pixel_aspect;
+        let height = plane_size.height as f32;
+
+        if width > height {
+            Vec2 { x: height / width, y: 1.0 }
+        }
+        else {
+            Vec2 { x: 1.0, y: width / height }
         }
     }
 
-    fn min_bounding_box(a: f32, b: f32, c: f32) -> f32 {
-        let mut bb = if a < b { a } else { b };
-        bb = if bb < c { bb } else { c };
-        bb
+    #[inline(always)]
+    fn to_buffer_coordinates(vec: Vec2, size: &Size, scale: &Vec2) -> (i32, i32) {
+        let position = Self::to_buffer_position(vec, size, scale);
+        (position.x as i32, position.y as i32)
     }
 
-    fn max_bounding_box(a: f32, b: f32, c: f32) -> f32 {
-        let mut bb = if a > b { a } else { b };
-        bb = if bb > c { bb } else { c };
-        bb
+    // Buffer coordinates before rounding to pixels
+    #[inline(always)]
+    fn to_buffer_position(vec: Vec2, size: &Size, scale: &Vec2) -> Vec2 {
+        Vec2 {
+            x: size.width as f32 * (vec.x * scale.x + 1.0) / 2.0,
+            y: size.height as f32 * (-vec.y * scale.y + 1.0) / 2.0
+        }
     }
 
-    pub fn camera(&mut self, eye: &Vec3, center: &Vec3, up: &Vec3) {
-        self.view_matrix = transform::look_at(eye, center, up);
+    #[inline(always)]
+    fn from_buffer_coordinates(x: i32, y: i32, size: &Size, scale: &Vec2) -> Vec2 {
+        Vec2 {
+            x: (x as f32 / size.width as f32 * 2.0 - 1.0) / scale.x,
+            y: (-y as f32 / size.height as f32 * 2.0 + 1.0) / scale.y
+        }
     }
 
-    pub fn light(&mut self, light_vector: &Vec3) {
-        self.light_vector = *light_vector;
-        self.shadow_view_matrix = transform::look_at(
-            &light_vector,
-            &Vec3::ZERO,
-            &Vec3 { x: 0.0, y: 1.0, z: 0.0 }
-        );
+    #[inline(always)]
+    fn to_renderer_coordinates(&self, x: i32, y: i32) -> Vec2 {
+        Self::from_buffer_coordinates(x, y, &self.drawer.plane_size(), &self.aspect_scale())
     }
 
-    fn transform(&self, p: &Vec3) -> Vec3 {
-        (self.projection_matrix * (self.view_matrix * p.homo_point())).point_proj()
+    // The shadow buffer is not corrected for the aspect ratio, as it is never displayed
+    #[inline(always)]
+    fn to_shadow_coordinates(&self, vec: Vec2) -> (i32, i32) {
+        Self::to_buffer_coordinates(vec, &self.shadow_size(), &Vec2 { x: 1.0, y: 1.0 })
     }
 
-    fn transform_normal(&self, p: &Vec3) -> Vec3 {
-        (
-            self.normal_projection_matrix * (self.view_matrix * p.homo_vector())
-        ).vector_proj()
+    #[inline(always)]
+    fn shadow_to_renderer_coordinates(&self, x: i32, y: i32) -> Vec2 {
+        Self::from_buffer_coordinates(x, y, &self.shadow_size(), &Vec2 { x: 1.0, y: 1.0 })
     }
 
-    fn transform_shadow(&self, p: &Vec3) -> Vec3 {
-        (
-           self.projection_matrix * (self.shadow_view_matrix * p.homo_point())
-        ).point_proj()
+    fn shadow_size(&self) -> Size {
+        match &self.shadow_resolution {
+            Some(resolution) => resolution.clone(),
+            None => self.drawer.plane_size()
+        }
     }
 
-    fn ambient_occlusion(&self, x: i32, y: i32) -> f32{
-        let mut ambient_light = 0.0;
-        let zbuffer_index = (y * self.drawer.plane_size().width + x) as usize;
+    pub fn set_shadow_resolution(&mut self, width: i32, height: i32) {
+        self.shadow_resolution = Some(Size { width, height });
+        self.invalidate_shadows();
+    }
 
-        for x_step in -1..=1 {
-            for y_step in -1..=1 {
-                if x_step == 0 && y_step == 0 {
-                    continue;
-                }
+    pub fn set_clear_color(&mut self, clear_color: Color) {
+        self.set_background(Background::Solid(clear_color));
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
 
-                let mut zbuffer_x = x;
-                let mut zbuffer_y = y;
-                let mut max_angle = 0.0;
+    pub fn refresh(&mut self) {
+        self.refresh_background(self.background);
+    }
+
+    // Clears the frame to the given color instead of the background
+    pub fn refresh_with(&mut self, color: &Color) {
+        self.refresh_background(Background::Solid(*color));
+    }
 
-                while self.ambient_occlusion_step(
-                    x, y,
-                    zbuffer_index, &mut zbuffer_x, &mut zbuffer_y,
-                    x_step, y_step, &mut max_angle
-                ) {}
+    fn refresh_background(&mut self, background: Background) {
+        if self.follows_terminal {
+            let (cols, rows) = Drawer::get_terminal_size();
+            let (cols, rows) = (cols as i32, rows as i32);
 
-                ambient_light += std::f32::consts::PI / 2.0 - max_angle;
+            if rows != self.drawer.win_size().rows || cols != self.drawer.win_size().cols {
+                self.resize(WinSize { cols, rows });
             }
         }
 
-        (ambient_light / 4.0 / std::f32::consts::PI).powi(40)
-    }
+        for p in self.zbuffer.iter_mut() {
+            *p = self.depth_mode.clear_value();
+        }
 
-    fn ambient_occlusion_step(
-        &self,
-        x: i32, y: i32,
-        zbuffer_index: usize, zbuffer_x: &mut i32, zbuffer_y: &mut i32,
-        x_step: i32, y_step: i32,
-        max_angle: &mut f32
-    ) -> bool {
-        *zbuffer_x += x_step;
-        *zbuffer_y += y_step;
+        for p in self.ao_buffer.iter_mut() {
+            *p = f32::NEG_INFINITY;
+        }
+
+        if self.static_shadows && self.shadow_map_filled {
+            self.shadow_map_cached = true;
+        }
 
-        if *zbuffer_x >= self.drawer.plane_size().width ||
-           *zbuffer_y >= self.drawer.plane_size().height ||
-           *zbuffer_x < 0 || *zbuffer_y < 0 {
-               return false
+        match background {
+            Background::Solid(color) => self.drawer.clear(&color),
+            Background::Gradient { top, bottom } => self.drawer.clear_gradient(&top, &bottom)
         }
 
-        let zbuffer_ray_index = (*zbuffer_y * self.drawer.plane_size().width + *zbuffer_x) as usize;
+        self.reset_stats();
+    }
 
-        if self.zbuffer[zbuffer_ray_index] == std::f32::NEG_INFINITY {
-            return true;
+    // Reallocates the buffers for a terminal of the given size,
+    // the projection adapts to the new aspect ratio by itself
+    pub fn resize(&mut self, win_size: WinSize) {
+        self.drawer.resize(win_size);
+        self.zbuffer = Self::create_zbuffer(self.drawer.plane_size(), self.depth_mode);
+        self.ao_buffer = self.create_ao_buffer();
+        if self.shadow_resolution.is_none() {
+            self.invalidate_shadows();
         }
+    }
 
-        let height = self.zbuffer[zbuffer_ray_index] - self.zbuffer[zbuffer_index];
-        let length = (((x - *zbuffer_x).pow(2) + (y - *zbuffer_y).pow(2)) as f32).sqrt();
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
 
-        let angle = (height / length).atan();
+    pub fn reset_stats(&mut self) {
+        self.stats = RenderStats::default();
+    }
 
-        if angle > *max_angle {
-            *max_angle = angle;
-        }
+    // Displaying can be skipped if the frame is the same as the previous one,
+    // the outline is found here already so that it is compared as well
+    pub fn frame_changed(&mut self) -> bool {
+        self.apply_edge_outline();
+        self.drawer.changed_pixels() > 0
+    }
 
-        true
+    // Depths of the drawing plane row by row, encoded according to the depth mode
+    pub fn zbuffer(&self) -> &[f32] {
+        &self.zbuffer
     }
 
-    pub fn triangle(
-        &mut self,
-        // Vertices
-        v1: &Vec3, v2: &Vec3, v3: &Vec3,
-        // UV coordinates
-        t1: &Vec2, t2: &Vec2, t3: &Vec2, texture: &Image,
-        // Normal vectors
-        n1: &Vec3, n2: &Vec3, n3: &Vec3, normal_map: &Image
-    ) {
-        // vertices used for calculating shadow buffer
-        let s1 = self.transform_shadow(v1);
-        let s2 = self.transform_shadow(v2);
-        let s3 = self.transform_shadow(v3);
-
-        self.fill_in_shadow_buffer(&s1, &s2, &s3);
-
-        // vertices
-        let p1 = self.transform(v1);
-        let p2 = self.transform(v2);
-        let p3 = self.transform(v3);
-
-        // normal vectors
-        let n1 = self.transform_normal(n1);
-        let n2 = self.transform_normal(n2);
-        let n3 = self.transform_normal(n3);
-
-        self.fill_in_triangle(
-            &p1, &p2, &p3,
-            &t1, &t2, &t3, texture,
-            &n1, &n2, &n3, &normal_map,
-            &s1, &s2, &s3
-        );
+    // Depths as seen from the shadow casting light, greater values are closer to it
+    pub fn shadow_buffer(&self) -> &[f32] {
+        &self.shadow_buffer
     }
 
-    fn fill_in_shadow_buffer(&mut self, s1: &Vec3, s2: &Vec3, s3: &Vec3) {
-        let shadow_bbox = self.bounding_box(&s1, &s2, &s3);
+    fn apply_edge_outline(&mut self) {
+        if let Some(outline) = &self.edge_outline {
+            let edges = self.depth_edges(outline.threshold);
+            self.drawer.set_outline(outline.color, edges);
+        }
+    }
 
-        for i in shadow_bbox.min_x..=shadow_bbox.max_x {
-            for j in shadow_bbox.min_y..=shadow_bbox.max_y {
-                let s = transform::to_barycentric(
-                    &Vec2 { x: s1.x, y: s1.y },
-                    &Vec2 { x: s2.x, y: s2.y },
-                    &Vec2 { x: s3.x, y: s3.y },
-                    &self.to_renderer_coordinates(i, j)
-                );
+    pub fn display(&mut self) -> Result<(), Error> {
+        self.apply_edge_outline();
+        self.stats.changed_pixels = self.drawer.changed_pixels();
 
-                if s.x >= 0.0 && s.y >= 0.0 && s.z >= 0.0 {
-                    let pixel_depth = s1.z * s.x + s2.z * s.y + s3.z * s.z;
-                    let shadow_buffer_index = (j * self.drawer.plane_size().width + i) as usize;
+        if let Some(pacer) = &self.pacer {
+            pacer.wait();
+        }
 
-                    if pixel_depth > self.shadow_buffer[shadow_buffer_index] {
-                        self.shadow_buffer[shadow_buffer_index] = pixel_depth;
-                    }
-                }
-            }
+        self.drawer.display()?;
+
+        if let Some(pacer) = &mut self.pacer {
+            pacer.frame_displayed(self.drawer.output_time());
         }
+
+        Ok(())
     }
 
-    fn fill_in_triangle(
-        &mut self,
-        // Vertices in barycentric coordinates
-        p1: &Vec3, p2: &Vec3, p3: &Vec3,
-        // UV coordinates
-        t1: &Vec2, t2: &Vec2, t3: &Vec2, texture: &Image,
-        // Normal vectors
-        n1: &Vec3, n2: &Vec3, n3: &Vec3, normal_map: &Image,
-        s1: &Vec3, s2: &Vec3, s3: &Vec3
-    ) {
-        let bbox = self.bounding_box(&p1, &p2, &p3);
-        let light_vector = self.transform_normal(&self.light_vector);
+    pub fn save_frame<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Error> {
+        self.drawer.to_image().save_tga(path)
+    }
 
-        for i in bbox.min_x..=bbox.max_x {
-            for j in bbox.min_y..=bbox.max_y {
-                let p = transform::to_barycentric(
-                    &Vec2 { x: p1.x, y: p1.y },
-                    &Vec2 { x: p2.x, y: p2.y },
-                    &Vec2 { x: p3.x, y: p3.y },
-                    &self.to_renderer_coordinates(i, j)
-                );
+    // Depth values mapped to black and white by render_depth, found automatically if not set
+    pub fn set_depth_range(&mut self, depth_range: Option<(f32, f32)>) {
+        self.depth_range = depth_range;
+    }
+
+    pub fn render_depth(&mut self) {
+        let (near, far) = match self.depth_range {
+            Some(range) => range,
+            None => self.finite_depth_range()
+        };
+
+        for y in 0..self.drawer.plane_size().height {
+            for x in 0..self.drawer.plane_size().width {
+                let depth = self.zbuffer[(y * self.drawer.plane_size().width + x) as usize];
 
-                if !(p.x >= 0.0 && p.y >= 0.0 && p.z >= 0.0) || 
-                   !self.update_zbuffer_and_check_if_visible(&p, &p1, &p2, &p3, i, j) {
+                if depth == self.depth_mode.clear_value() {
+                    self.drawer.set_vertex(x, y, &Color::BLACK);
                     continue;
                 }
 
-                let texture_coordinates = Self::calc_texture_coords(&t1, &t2, &t3, &p, texture);
+                let depth = self.depth_mode.decode(depth);
 
-                let normal_vector = match Self::calc_normal_vector(
-                    n1, n2, n3, &p, &p1, &p2, &p3, &t1, &t2, &t3, texture_coordinates, &normal_map
-                    ) {
-                    Some(vec) => vec,
-                    None => continue,
-                };
+                // Greater depth values are closer to the camera
+                let brightness = if near == far { 1.0 } else { (depth - far) / (near - far) };
+                self.drawer.set_vertex(x, y, &(Color::WHITE * brightness));
+            }
+        }
+    }
 
-                let shadow_light = self.calc_shadow_light(&p, s1, s2, s3);
-                let light_intensity = self.calc_light_intensity(
-                    &light_vector, &normal_vector, shadow_light, i, j
-                );
+    // Depth normalized like in render_depth, row by row from the top.
+    // Empty pixels are 0, the farthest depth is 1 and the nearest 65535
+    pub fn depth_image(&self) -> Vec<u16> {
+        let (near, far) = match self.depth_range {
+            Some(range) => range,
+            None => self.finite_depth_range()
+        };
 
-                self.drawer.set_vertex(
-                    i, j,
-                    &(*texture.at(texture_coordinates.0, texture_coordinates.1) * light_intensity)
-                );
+        self.zbuffer.iter().map(|&depth| {
+            if depth == self.depth_mode.clear_value() {
+                return 0;
             }
+
+            let depth = self.depth_mode.decode(depth);
+            let brightness = if near == far { 1.0 } else { (depth - far) / (near - far) };
+
+            1 + (brightness.clamp(0.0, 1.0) * f32::from(u16::MAX - 1)).round() as u16
+        }).collect()
+    }
+
+    // Binary 16-bit PGM, which most image tools can read
+    pub fn save_depth_pgm<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Error> {
+        let size = self.drawer.plane_size();
+        let mut file_buffer = format!("P5\n{} {}\n{}\n", size.width, size.height, u16::MAX).into_bytes();
+
+        // Samples wider than a byte are big-endian
+        for depth in self.depth_image() {
+            file_buffer.extend_from_slice(&depth.to_be_bytes());
         }
+
+        std::fs::write(path, file_buffer)?;
+
+        Ok(())
     }
 
-    fn update_zbuffer_and_check_if_visible(
-        &mut self,
-        p: &Vec3, p1: &Vec3, p2: &Vec3, p3: &Vec3,
-        i: i32, j: i32
-    ) -> bool {
-        let pixel_depth = p1.z * p.x + p2.z * p.y + p3.z * p.z;
-        let zbuffer_index = (j * self.drawer.plane_size().width + i) as usize;
+    // Returns the (nearest, farthest) depth found in the z-buffer
+    fn finite_depth_range(&self) -> (f32, f32) {
+        let mut near = f32::NEG_INFINITY;
+        let mut far = f32::INFINITY;
 
-        if pixel_depth <= self.zbuffer[zbuffer_index] {
-            return false;
+        for &depth in self.zbuffer.iter().filter(|depth| depth.is_finite()) {
+            let depth = self.depth_mode.decode(depth);
+            near = if depth > near { depth } else { near };
+            far = if depth < far { depth } else { far };
         }
 
-        self.zbuffer[zbuffer_index] = pixel_depth;
-        true
+        (near, far)
     }
 
-    fn calc_texture_coords(
-        t1: &Vec2, t2: &Vec2, t3: &Vec2,
-        p: &Vec3, texture: &Image
-    ) -> (usize, usize) {
-        let uv_coordinates = transform::to_euclidean(t1, t2, t3, &p);
-        (
-            (uv_coordinates.x * (texture.size().width - 1) as f32) as usize,
-            (uv_coordinates.y * (texture.size().height - 1) as f32) as usize,
+    fn bounding_box(&self, p1: &Vec3, p2: &Vec3, p3: &Vec3) -> BoundingBox {
+        Self::buffer_bounding_box(p1, p2, p3, &self.drawer.plane_size(), &self.aspect_scale())
+    }
+
+    fn buffer_bounding_box(p1: &Vec3, p2: &Vec3, p3: &Vec3, size: &Size, scale: &Vec2) -> BoundingBox {
+        let min = Self::to_buffer_position(Vec2 {
+            x: Self::min_bounding_box(p1.x, p2.x, p3.x),
+            y: Self::max_bounding_box(p1.y, p2.y, p3.y)
+        }, size, scale);
+
+        let max = Self::to_buffer_position(Vec2 {
+            x: Self::max_bounding_box(p1.x, p2.x, p3.x),
+            y: Self::min_bounding_box(p1.y, p2.y, p3.y)
+        }, size, scale);
+
+        // Rounded outwards, pixels outside of the triangle are rejected later anyway.
+        // Casting saturates, so huge triangles are clamped correctly
+        BoundingBox {
+            min_x: std::cmp::max(min.x.floor() as i32, 0),
+            max_x: std::cmp::min(max.x.ceil() as i32, size.width - 1),
+            min_y: std::cmp::max(min.y.floor() as i32, 0),
+            max_y: std::cmp::min(max.y.ceil() as i32, size.height - 1)
+        }
+    }
+
+    fn min_bounding_box(a: f32, b: f32, c: f32) -> f32 {
+        let mut bb = if a < b { a } else { b };
+        bb = if bb < c { bb } else { c };
+        bb
+    }
+
+    fn max_bounding_box(a: f32, b: f32, c: f32) -> f32 {
+        let mut bb = if a > b { a } else { b };
+        bb = if bb > c { bb } else { c };
+        bb
+    }
+
+    pub fn camera(&mut self, eye: &Vec3, center: &Vec3, up: &Vec3) {
+        self.view_matrix = self.handedness.view_axes() * transform::look_at(eye, center, up);
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.view_matrix = self.handedness.view_axes() * camera.view_matrix();
+    }
+
+    // Transformation from world space to camera space, including the handedness convention
+    pub fn view_matrix(&self) -> &Matrix4 {
+        &self.view_matrix
+    }
+
+    // Models, cameras and lights are all given in the same convention,
+    // so models exported from left-handed tools do not have to be mirrored
+    pub fn set_handedness(&mut self, handedness: Handedness) {
+        // Mirroring is its own inverse, so the camera does not have to be set again
+        if handedness != self.handedness {
+            self.view_matrix = Handedness::Left.view_axes() * self.view_matrix;
+        }
+
+        self.handedness = handedness;
+    }
+
+    // Replaces all lights with a single directional light
+    pub fn light(&mut self, light_vector: &Vec3) {
+        self.clear_lights();
+        self.add_light(Light::Directional { dir: *light_vector });
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        if let Light::Directional { dir } = light {
+            if !self.has_shadow_light {
+                // Setting the same light again keeps the shadow buffer
+                let shadow_view_matrix = Self::shadow_view_matrix(&dir);
+                if shadow_view_matrix != self.shadow_view_matrix {
+                    self.shadow_view_matrix = shadow_view_matrix;
+                    self.invalidate_shadows();
+                }
+
+                self.has_shadow_light = true;
+            }
+        }
+
+        self.lights.push(light);
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.lights.clear();
+        self.has_shadow_light = false;
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    fn shadow_view_matrix(light_vector: &Vec3) -> Matrix4 {
+        transform::look_at(
+            light_vector,
+            &Vec3::ZERO,
+            &Vec3 { x: 0.0, y: 1.0, z: 0.0 }
         )
     }
 
-    fn calc_normal_vector(
-        n1: &Vec3, n2: &Vec3, n3: &Vec3,
-        p: &Vec3, p1: &Vec3, p2: &Vec3, p3: &Vec3,
-        t1: &Vec2, t2: &Vec2, t3: &Vec2,
-        texture_coordinates: (usize, usize), normal_map: &Image
-    ) -> Option<Vec3> {
-        // Tangent basis
-        let n_vector = (*n1 * p.x + *n2 * p.y + *n3 * p.z).normalized();
+    // Fragments between near and far view distance are linearly blended with the fog color
+    pub fn set_fog(&mut self, color: Color, near: f32, far: f32) {
+        self.fog = Some(Fog { color, near, far });
+    }
 
-        let darboux_matrix = match Self::calc_darboux_matrix(&p1, &p2, &p3, &n_vector) {
-            Some(matrix) => matrix,
-            None => return None
+    // Pixels much nearer than one of their neighbours are drawn with the color when displayed
+    pub fn set_edge_outline(&mut self, threshold: f32, color: Color) {
+        self.edge_outline = Some(EdgeOutline { threshold, color });
+    }
+
+    pub fn set_shading_mode(&mut self, shading_mode: ShadingMode) {
+        self.shading_mode = shading_mode;
+    }
+
+    pub fn shading_mode(&self) -> ShadingMode {
+        self.shading_mode
+    }
+
+    pub fn set_specular_model(&mut self, specular_model: SpecularModel) {
+        self.specular_model = specular_model;
+    }
+
+    // Depth discontinuities found in the z-buffer, marked on the nearer side
+    fn depth_edges(&self, threshold: f32) -> Vec<bool> {
+        let size = self.drawer.plane_size();
+        let depth_at = |x: i32, y: i32| self.depth_mode.decode(self.zbuffer[(y * size.width + x) as usize]);
+        let mut edges = vec![false; self.zbuffer.len()];
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let index = (y * size.width + x) as usize;
+                if self.zbuffer[index] == self.depth_mode.clear_value() {
+                    continue;
+                }
+
+                let depth = depth_at(x, y);
+                edges[index] = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    nx >= 0 && nx < size.width && ny >= 0 && ny < size.height &&
+                        depth - depth_at(nx, ny) > threshold
+                });
+            }
+        }
+
+        edges
+    }
+
+    fn apply_fog(&self, color: &Color, position: &Vec3) -> Color {
+        let fog = match &self.fog {
+            Some(fog) => fog,
+            None => return *color
         };
 
-        let i_vector = darboux_matrix * Vec3 { x: t2.x - t1.x, y: t3.x - t1.x, z: 0.0 };
-        let j_vector = darboux_matrix * Vec3 { x: t2.y - t1.y, y: t3.y - t1.y, z: 0.0 };
+        // The camera looks in the -z direction
+        let depth = -(self.view_matrix * position.homo_point()).z;
+        let fog_factor = ((depth - fog.near) / (fog.far - fog.near)).clamp(0.0, 1.0);
 
-        let normal_color = normal_map.at(texture_coordinates.0, texture_coordinates.1);
+        color.mix(&fog.color, fog_factor)
+    }
 
-        Some((
-            (f32::from(normal_color.r) / 255.0).powi(3) * i_vector.normalized() +
-            (f32::from(normal_color.g) / 255.0).powi(3) * j_vector.normalized() +
-            (f32::from(normal_color.b) / 255.0).powi(3) * n_vector
-        ).normalized())
+    fn transform(&self, p: &Vec3) -> Option<Vec3> {
+        (self.projection_matrix * (self.view_matrix * p.homo_point())).try_point_proj()
     }
 
-    fn calc_darboux_matrix(p1: &Vec3, p2: &Vec3, p3: &Vec3, n_vector: &Vec3) -> Option<Matrix3> {
-        Matrix3::new([
-            [p2.x - p1.x, p2.y - p1.y, p2.z - p1.z],
-            [p3.x - p1.x, p3.y - p1.y, p3.z - p1.z],
-            [n_vector.x, n_vector.y, n_vector.z]
-        ]).inverse()
+    // Point in world space seen at the pixel with the given depth, the inverse of transform
+    pub fn unproject(&self, x: i32, y: i32, depth: f32) -> Option<Vec3> {
+        let point = self.to_renderer_coordinates(x, y);
+        let inverse = (self.projection_matrix * self.view_matrix).inverse()?;
+
+        (inverse * Vec4 { x: point.x, y: point.y, z: depth, w: 1.0 }).try_point_proj()
     }
 
-    fn calc_shadow_light(&self, p: &Vec3, s1: &Vec3, s2: &Vec3, s3: &Vec3) -> f32 {
-        let shadow_vector = p.x * *s1 + p.y * *s2 + p.z * *s3;
+    fn transform_normal(&self, p: &Vec3) -> Vec3 {
+        (
+            self.normal_projection_matrix * (self.view_matrix * p.homo_vector())
+        ).vector_proj()
+    }
 
-        let shadow_coordinates = self.to_drawer_coordinates(
-            Vec2 { x: shadow_vector.x, y: shadow_vector.y }
+    fn transform_shadow(&self, p: &Vec3) -> Option<Vec3> {
+        (
+           self.projection_matrix * (self.shadow_view_matrix * p.homo_point())
+        ).try_point_proj()
+    }
+
+    // Takes effect from the next frame, as the downsampled buffer is filled during rasterization
+    pub fn set_ao_quality(&mut self, ao_quality: AoQuality) {
+        self.ao_quality = ao_quality;
+        self.ao_buffer = self.create_ao_buffer();
+    }
+
+    pub fn set_ao_radius(&mut self, ao_radius: Option<i32>) {
+        self.ao_radius = ao_radius;
+    }
+
+    fn ao_buffer_size(&self) -> Size {
+        let scale = self.ao_quality.scale();
+        let plane_size = self.drawer.plane_size();
+
+        Size {
+            width: (plane_size.width as u32).div_ceil(scale as u32) as i32,
+            height: (plane_size.height as u32).div_ceil(scale as u32) as i32
+        }
+    }
+
+    fn create_ao_buffer(&self) -> Vec<f32> {
+        match self.ao_quality {
+            AoQuality::Full | AoQuality::Off => Vec::new(),
+            AoQuality::Half => Self::create_zbuffer(self.ao_buffer_size(), DepthMode::Standard)
+        }
+    }
+
+    fn ambient_occlusion(&self, x: i32, y: i32) -> f32{
+        if self.ao_quality == AoQuality::Off {
+            return 1.0;
+        }
+
+        let mut ambient_light = 0.0;
+
+        let buffer = match self.ao_quality {
+            AoQuality::Full | AoQuality::Off => AoBuffer {
+                depths: &self.zbuffer,
+                depth_mode: self.depth_mode,
+                size: self.drawer.plane_size(),
+                scale: 1,
+                radius: self.ao_radius
+            },
+            AoQuality::Half => AoBuffer {
+                depths: &self.ao_buffer,
+                depth_mode: DepthMode::Standard,
+                size: self.ao_buffer_size(),
+                scale: self.ao_quality.scale(),
+                radius: self.ao_radius
+            }
+        };
+
+        let x = x / buffer.scale;
+        let y = y / buffer.scale;
+        // Compared with the depth of its own cell, the cell of a flat surface is not occluded by its neighbours
+        let depth = buffer.depth_mode.decode(buffer.depths[(y * buffer.size.width + x) as usize]);
+
+        for x_step in -1..=1 {
+            for y_step in -1..=1 {
+                if x_step == 0 && y_step == 0 {
+                    continue;
+                }
+
+                ambient_light += std::f32::consts::PI / 2.0 - buffer.horizon_angle(x, y, depth, x_step, y_step);
+            }
+        }
+
+        (ambient_light / 4.0 / std::f32::consts::PI).powi(40)
+    }
+
+    pub fn triangle(&mut self, vertices: &[Vertex; 3], shader: &dyn Shader) {
+        self.colored_triangle(vertices, None, shader);
+    }
+
+    pub fn colored_triangle(
+        &mut self,
+        vertices: &[Vertex; 3],
+        // Vertex colors, used instead of the texture if present
+        colors: Option<&[Color; 3]>,
+        shader: &dyn Shader
+    ) {
+        let projected = [
+            self.project_vertex(&shader.vertex(&vertices[0].position)),
+            self.project_vertex(&shader.vertex(&vertices[1].position)),
+            self.project_vertex(&shader.vertex(&vertices[2].position))
+        ];
+
+        self.projected_triangle(
+            [&projected[0], &projected[1], &projected[2]],
+            [&vertices[0].uv, &vertices[1].uv, &vertices[2].uv],
+            [
+                self.transform_normal(&vertices[0].normal),
+                self.transform_normal(&vertices[1].normal),
+                self.transform_normal(&vertices[2].normal)
+            ],
+            colors,
+            None,
+            shader
         );
+    }
+
+    // Square of size by size pixels centered on a world space point, hidden behind nearer geometry
+    pub fn draw_marker(&mut self, pos: &Vec3, color: &Color, size: i32) {
+        let clip = self.clip_position(pos);
+        if clip.w <= Self::NEAR_PLANE_W || size <= 0 {
+            return;
+        }
+
+        let screen = clip.point_proj();
+        let plane_size = self.drawer.plane_size();
+        let (x, y) = Self::to_buffer_coordinates(
+            Vec2 { x: screen.x, y: screen.y }, &plane_size, &self.aspect_scale()
+        );
+
+        let min_x = std::cmp::max(x - size / 2, 0);
+        let min_y = std::cmp::max(y - size / 2, 0);
+        let max_x = std::cmp::min(x - size / 2 + size - 1, plane_size.width - 1);
+        let max_y = std::cmp::min(y - size / 2 + size - 1, plane_size.height - 1);
+        let stored_depth = self.depth_mode.encode(screen.z);
 
-        let shadow_buffer_index = (
-            shadow_coordinates.1 * self.drawer.plane_size().width +
-            shadow_coordinates.0) as usize;
+        for i in min_x..=max_x {
+            for j in min_y..=max_y {
+                let zbuffer_index = (j * plane_size.width + i) as usize;
+                if !self.depth_mode.is_nearer(stored_depth, self.zbuffer[zbuffer_index]) {
+                    continue;
+                }
 
-        if self.shadow_buffer[shadow_buffer_index] > shadow_vector.z + 0.2 {
-            -1.0
+                self.zbuffer[zbuffer_index] = stored_depth;
+                self.drawer.point(i, j, color);
+            }
         }
-        else {
-            0.0
+    }
+
+    // Draws the silhouette edges of a mesh as seen along the view direction over the rendered model
+    pub fn draw_outline(&mut self, mesh: &Mesh, color: &Color, pos: &Vec3) {
+        // The camera looks in the -k direction of its basis, which is the third row of the view matrix
+        let view_direction = -1.0 * Vec3 {
+            x: self.view_matrix[(2, 0)],
+            y: self.view_matrix[(2, 1)],
+            z: self.view_matrix[(2, 2)]
+        };
+
+        for [a, b] in mesh.silhouette_edges(&view_direction) {
+            let c1 = self.clip_position(&(*mesh.vertex(a) + *pos));
+            let c2 = self.clip_position(&(*mesh.vertex(b) + *pos));
+
+            if c1.w > Self::NEAR_PLANE_W && c2.w > Self::NEAR_PLANE_W {
+                self.depth_tested_line(&c1.point_proj(), &c2.point_proj(), color);
+            }
         }
     }
 
-    fn calc_light_intensity(
-        &self,
-        light_vector: &Vec3,
-        normal_vector: &Vec3,
-        shadow_light: f32,
-        i: i32, j: i32
-    ) -> f32 {
-        let reflection_vector =
-            2.0 * *normal_vector * (*normal_vector * *light_vector) - *light_vector;
+    fn depth_tested_line(&mut self, p1: &Vec3, p2: &Vec3, color: &Color) {
+        let plane_size = self.drawer.plane_size();
+        let scale = self.aspect_scale();
+        let start = Self::to_buffer_position(Vec2 { x: p1.x, y: p1.y }, &plane_size, &scale);
+        let end = Self::to_buffer_position(Vec2 { x: p2.x, y: p2.y }, &plane_size, &scale);
 
-        let specular_light = (reflection_vector * Vec3 { x: 0.0, y: 0.0, z: 1.0 }).powi(35);
-        let diffuse_light = *normal_vector * *light_vector;
-        let ambient_light = self.ambient_occlusion(i, j);
-
-        specular_light * 0.7 +
-        diffuse_light * 1.0 +
-        ambient_light * 0.4 +
-        shadow_light * 0.2
-    }
-
-    pub fn model(&mut self, mesh: &Mesh, texture: &Image, normal_map: &Image, pos: &Vec3) {
-        for face in mesh.faces() {
-            let vertices = [
-                *mesh.vertex(face.vertices[0]) + *pos,
-                *mesh.vertex(face.vertices[1]) + *pos,
-                *mesh.vertex(face.vertices[2]) + *pos
-            ];
-
-            let uv_coordinates = [
-                mesh.texture_coord(face.texture_coords[0]),
-                mesh.texture_coord(face.texture_coords[1]),
-                mesh.texture_coord(face.texture_coords[2])
-            ];
-
-            let normal_vectors = [
-                mesh.normal(face.normals[0]),
-                mesh.normal(face.normals[1]),
-                mesh.normal(face.normals[2]),
-            ];
-
-            self.triangle(
-                &vertices[0], &vertices[1], &vertices[2],
-                &uv_coordinates[0], &uv_coordinates[1], &uv_coordinates[2], &texture,
-                &normal_vectors[0], &normal_vectors[1], &normal_vectors[2], &normal_map
+        let steps = (end.x - start.x).abs().max((end.y - start.y).abs()).ceil().max(1.0) as i32;
+
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let position = start + t * (end - start);
+            let depth = p1.z + t * (p2.z - p1.z);
+
+            for dx in 0..Self::OUTLINE_WIDTH {
+                for dy in 0..Self::OUTLINE_WIDTH {
+                    let x = position.x as i32 + dx - Self::OUTLINE_WIDTH / 2;
+                    let y = position.y as i32 + dy - Self::OUTLINE_WIDTH / 2;
+
+                    if x < 0 || y < 0 || x >= plane_size.width || y >= plane_size.height {
+                        continue;
+                    }
+
+                    // Empty pixels decode to negative infinity
+                    let stored_depth = self.depth_mode.decode(self.zbuffer[(y * plane_size.width + x) as usize]);
+                    if depth + Self::OUTLINE_DEPTH_BIAS < stored_depth {
+                        continue;
+                    }
+
+                    self.drawer.point(x, y, color);
+                }
+            }
+        }
+    }
+
+    fn project_vertex(&self, position: &Vec3) -> ProjectedVertex {
+        ProjectedVertex {
+            position: *position,
+            clip: self.clip_position(position),
+            shadow: self.transform_shadow(position)
+        }
+    }
+
+    // Normal vectors are already transformed
+    fn projected_triangle(
+        &mut self,
+        vertices: [&ProjectedVertex; 3],
+        uv: [&Vec2; 3],
+        normals: [Vec3; 3],
+        colors: Option<&[Color; 3]>,
+        // Baked ambient occlusion, used instead of the screen space one if present
+        ambient_occlusion: Option<[f32; 3]>,
+        shader: &dyn Shader
+    ) {
+        self.stats.triangles += 1;
+
+        let [p1, p2, p3] = vertices;
+
+        // Triangles with a vertex on the plane of the light neither cast nor receive shadows
+        let shadow = match (p1.shadow, p2.shadow, p3.shadow) {
+            (Some(s1), Some(s2), Some(s3)) => Some([s1, s2, s3]),
+            _ => None
+        };
+
+        if self.has_shadow_light && self.shadows && !self.depth_only && !self.shadow_map_cached {
+            if let Some([s1, s2, s3]) = &shadow {
+                self.fill_in_shadow_buffer(s1, s2, s3);
+                self.shadow_map_filled = true;
+            }
+        }
+
+        // Culled after filling in the shadow buffer, as invisible triangles still cast shadows
+        if self.outside_frustum(&p1.clip, &p2.clip, &p3.clip) {
+            self.stats.triangles_culled += 1;
+            self.stats.triangles_frustum_culled += 1;
+            return;
+        }
+
+        let normals = match self.shading_mode {
+            ShadingMode::Smooth | ShadingMode::Gouraud => normals,
+            ShadingMode::Flat => {
+                let face_normal = self.handedness.cross(
+                    &(p2.position - p1.position), &(p3.position - p1.position)
+                ).normalized();
+                let face_normal = self.transform_normal(&face_normal);
+                [face_normal, face_normal, face_normal]
+            }
+        };
+
+        let uv = uv.map(|uv| self.oriented_uv(uv));
+
+        // Triangles in front of the near plane use the already projected vertices
+        if vertices.iter().all(|p| p.clip.w > Self::NEAR_PLANE_W) {
+            let triangle = TriangleAttributes {
+                screen: [p1.clip.point_proj(), p2.clip.point_proj(), p3.clip.point_proj()],
+                world: [p1.position, p2.position, p3.position],
+                uv,
+                normals,
+                shadow,
+                colors: colors.copied(),
+                ambient_occlusion
+            };
+
+            self.fill_in_triangle(&triangle, shader);
+            return;
+        }
+
+        let [c1, c2, c3] = match colors {
+            Some(colors) => *colors,
+            None => [Color::WHITE; 3]
+        };
+
+        let [ao1, ao2, ao3] = ambient_occlusion.unwrap_or([1.0; 3]);
+
+        let polygon = self.clip_near_plane([
+            ClipVertex { position: p1.position, uv: uv[0], normal: normals[0], color: c1, ambient_occlusion: ao1 },
+            ClipVertex { position: p2.position, uv: uv[1], normal: normals[1], color: c2, ambient_occlusion: ao2 },
+            ClipVertex { position: p3.position, uv: uv[2], normal: normals[2], color: c3, ambient_occlusion: ao3 }
+        ]);
+
+        if polygon.len() < 3 {
+            self.stats.triangles_culled += 1;
+        }
+
+        // Clipping a triangle with a plane results in a convex polygon
+        for i in 1..polygon.len().saturating_sub(1) {
+            self.clipped_triangle(
+                &polygon[0], &polygon[i], &polygon[i + 1],
+                colors.is_some(), ambient_occlusion.is_some(), shader
             );
         }
     }
-} 
+
+    fn clipped_triangle(
+        &mut self,
+        c1: &ClipVertex, c2: &ClipVertex, c3: &ClipVertex,
+        has_colors: bool,
+        has_ambient_occlusion: bool,
+        shader: &dyn Shader
+    ) {
+        // Degenerate vertices are skipped together with their triangle
+        let screen = match (
+            self.transform(&c1.position),
+            self.transform(&c2.position),
+            self.transform(&c3.position)
+        ) {
+            (Some(s1), Some(s2), Some(s3)) => [s1, s2, s3],
+            _ => return
+        };
+
+        let shadow = match (
+            self.transform_shadow(&c1.position),
+            self.transform_shadow(&c2.position),
+            self.transform_shadow(&c3.position)
+        ) {
+            (Some(s1), Some(s2), Some(s3)) => Some([s1, s2, s3]),
+            _ => None
+        };
+
+        let triangle = TriangleAttributes {
+            screen,
+            world: [c1.position, c2.position, c3.position],
+            uv: [c1.uv, c2.uv, c3.uv],
+            normals: [c1.normal, c2.normal, c3.normal],
+            shadow,
+            colors: if has_colors { Some([c1.color, c2.color, c3.color]) } else { None },
+            ambient_occlusion: if has_ambient_occlusion {
+                Some([c1.ambient_occlusion, c2.ambient_occlusion, c3.ambient_occlusion])
+            }
+            else {
+                None
+            }
+        };
+
+        self.fill_in_triangle(&triangle, shader);
+    }
+
+    fn clip_position(&self, p: &Vec3) -> Vec4 {
+        self.projection_matrix * (self.view_matrix * p.homo_point())
+    }
+
+    fn clip_w(&self, p: &Vec3) -> f32 {
+        self.clip_position(p).w
+    }
+
+    // Triangles with all vertices beyond the same side of the view frustum are not visible
+    fn outside_frustum(&self, c1: &Vec4, c2: &Vec4, c3: &Vec4) -> bool {
+        // Visible renderer coordinates range from -1 / scale to 1 / scale
+        let scale = self.aspect_scale();
+        let clip = [c1, c2, c3];
+
+        clip.iter().all(|c| c.x * scale.x > c.w) ||
+        clip.iter().all(|c| c.x * scale.x < -c.w) ||
+        clip.iter().all(|c| c.y * scale.y > c.w) ||
+        clip.iter().all(|c| c.y * scale.y < -c.w)
+    }
+
+    // Sutherland-Hodgman clipping against the w = NEAR_PLANE_W plane
+    fn clip_near_plane(&self, vertices: [ClipVertex; 3]) -> Vec<ClipVertex> {
+        let mut polygon = Vec::with_capacity(4);
+
+        for i in 0..3 {
+            let current = &vertices[i];
+            let next = &vertices[(i + 1) % 3];
+            let current_w = self.clip_w(&current.position);
+            let next_w = self.clip_w(&next.position);
+
+            if current_w > Self::NEAR_PLANE_W {
+                polygon.push(*current);
+            }
+
+            if (current_w > Self::NEAR_PLANE_W) != (next_w > Self::NEAR_PLANE_W) {
+                let t = (Self::NEAR_PLANE_W - current_w) / (next_w - current_w);
+                polygon.push(current.lerp(next, t));
+            }
+        }
+
+        polygon
+    }
+
+    fn fill_in_shadow_buffer(&mut self, s1: &Vec3, s2: &Vec3, s3: &Vec3) {
+        let shadow_size = self.shadow_size();
+        let shadow_bbox = Self::buffer_bounding_box(s1, s2, s3, &shadow_size, &Vec2 { x: 1.0, y: 1.0 });
+
+        for i in shadow_bbox.min_x..=shadow_bbox.max_x {
+            for j in shadow_bbox.min_y..=shadow_bbox.max_y {
+                let s = transform::to_barycentric(
+                    &Vec2 { x: s1.x, y: s1.y },
+                    &Vec2 { x: s2.x, y: s2.y },
+                    &Vec2 { x: s3.x, y: s3.y },
+                    &self.shadow_to_renderer_coordinates(i, j)
+                );
+
+                if s.x >= 0.0 && s.y >= 0.0 && s.z >= 0.0 {
+                    let pixel_depth = s1.z * s.x + s2.z * s.y + s3.z * s.z;
+                    let shadow_buffer_index = (j * shadow_size.width + i) as usize;
+
+                    if pixel_depth > self.shadow_buffer[shadow_buffer_index] {
+                        self.shadow_buffer[shadow_buffer_index] = pixel_depth;
+                    }
+                }
+            }
+        }
+    }
+
+    // Edge function of the line through a and b, evaluated with the endpoints in a fixed order,
+    // so that triangles sharing an edge get exactly opposite values on it
+    fn edge_function(a: &Vec2, b: &Vec2, p: &Vec2) -> f32 {
+        let (a, b, sign) = if (a.x, a.y) < (b.x, b.y) { (a, b, 1.0) } else { (b, a, -1.0) };
+        sign * ((b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x))
+    }
+
+    // Top edges are horizontal with the triangle below them,
+    // left edges have the triangle on their right
+    fn is_top_left_edge(a: &Vec2, b: &Vec2, opposite: &Vec2) -> bool {
+        let edge = *b - *a;
+
+        if edge.y == 0.0 {
+            return opposite.y < a.y;
+        }
+
+        let side = edge.y * (opposite.x - a.x) - edge.x * (opposite.y - a.y);
+        side * edge.y > 0.0
+    }
+
+    // Edge function of vertices given in fractions of a pixel, evaluated exactly
+    fn fixed_point_edge_function(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+        (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+    }
+
+    // The same rule as is_top_left_edge, but buffer coordinates grow downwards
+    fn is_top_left_fixed_point_edge(a: (i64, i64), b: (i64, i64), opposite: (i64, i64)) -> bool {
+        let edge = (b.0 - a.0, b.1 - a.1);
+
+        if edge.1 == 0 {
+            return opposite.1 > a.1;
+        }
+
+        let side = edge.1 * (opposite.0 - a.0) - edge.0 * (opposite.1 - a.1);
+        side.signum() * edge.1.signum() > 0
+    }
+
+    fn fill_in_triangle(&mut self, triangle: &TriangleAttributes, shader: &dyn Shader) {
+        let [p1, p2, p3] = &triangle.screen;
+        let mut bbox = self.bounding_box(p1, p2, p3);
+
+        // Samples of antialiased pixels reach into the neighbouring rows
+        if self.antialiasing {
+            bbox.min_y = std::cmp::max(bbox.min_y - 1, 0);
+            bbox.max_y = std::cmp::min(bbox.max_y + 1, self.drawer.plane_size().height - 1);
+        }
+
+        let vertex_light = if self.shading_mode == ShadingMode::Gouraud && !self.depth_only {
+            let shininess = shader.shininess();
+            Some([
+                self.calc_direct_light(&triangle.normals[0].normalized(), &triangle.world[0], shininess),
+                self.calc_direct_light(&triangle.normals[1].normalized(), &triangle.world[1], shininess),
+                self.calc_direct_light(&triangle.normals[2].normalized(), &triangle.world[2], shininess)
+            ])
+        }
+        else {
+            None
+        };
+
+        match self.rasterizer {
+            Rasterizer::FixedPoint => self.rasterize_fixed_point(triangle, shader, &bbox, &vertex_light),
+            Rasterizer::Float => self.rasterize_float(triangle, shader, &bbox, &vertex_light)
+        }
+    }
+
+    fn rasterize_fixed_point(
+        &mut self,
+        triangle: &TriangleAttributes,
+        shader: &dyn Shader,
+        bbox: &BoundingBox,
+        vertex_light: &Option<[(f32, f32); 3]>
+    ) {
+        let size = self.drawer.plane_size();
+        let scale = self.aspect_scale();
+
+        // Pixels are sampled at their corners, which lie on whole buffer coordinates
+        let to_fixed_point = |p: &Vec3| {
+            let position = Self::to_buffer_position(Vec2 { x: p.x, y: p.y }, &size, &scale);
+            (
+                (position.x * Self::SUBPIXEL_STEPS as f32).round() as i64,
+                (position.y * Self::SUBPIXEL_STEPS as f32).round() as i64
+            )
+        };
+
+        let vertices = [
+            to_fixed_point(&triangle.screen[0]),
+            to_fixed_point(&triangle.screen[1]),
+            to_fixed_point(&triangle.screen[2])
+        ];
+
+        let area = Self::fixed_point_edge_function(vertices[0], vertices[1], vertices[2]);
+        if area == 0 {
+            return;
+        }
+
+        // Edge functions are made positive inside the triangle
+        let sign = area.signum();
+        let start = (
+            i64::from(bbox.min_x) * Self::SUBPIXEL_STEPS,
+            i64::from(bbox.min_y) * Self::SUBPIXEL_STEPS
+        );
+
+        let mut top_left = [false; 3];
+        // Changes of the edge functions per subpixel step down and per pixel to the right
+        let mut subpixel_step_y = [0; 3];
+        let mut step_x = [0; 3];
+        let mut column_start = [0; 3];
+
+        // Edges are opposite to the vertices with the same index
+        for k in 0..3 {
+            let a = vertices[(k + 1) % 3];
+            let b = vertices[(k + 2) % 3];
+
+            top_left[k] = Self::is_top_left_fixed_point_edge(a, b, vertices[k]);
+            subpixel_step_y[k] = sign * (b.0 - a.0);
+            step_x[k] = -sign * (b.1 - a.1) * Self::SUBPIXEL_STEPS;
+            column_start[k] = sign * Self::fixed_point_edge_function(a, b, start);
+        }
+
+        // Pixels lying exactly on an edge are drawn only by the triangle to which
+        // the edge is a top or left one
+        let inside = |edge: &[i64; 3], subpixel_offset: i64| (0..3).all(|k| {
+            let edge = edge[k] + subpixel_offset * subpixel_step_y[k];
+            edge > 0 || (edge == 0 && top_left[k])
+        });
+
+        let area = area.abs() as f32;
+
+        for i in bbox.min_x..=bbox.max_x {
+            let mut edge = column_start;
+
+            for j in bbox.min_y..=bbox.max_y {
+                if j > bbox.min_y {
+                    for k in 0..3 {
+                        edge[k] += subpixel_step_y[k] * Self::SUBPIXEL_STEPS;
+                    }
+                }
+
+                if self.antialiasing {
+                    // Coverage is estimated with samples spread vertically across the pixel,
+                    // which smooths nearly horizontal edges
+                    let covered = (0..Self::COVERAGE_SAMPLES).filter(|&k| {
+                        let offset = (k as f32 + 0.5) / Self::COVERAGE_SAMPLES as f32 - 0.5;
+                        // Buffer coordinates grow downwards
+                        inside(&edge, -(offset * Self::SUBPIXEL_STEPS as f32).round() as i64)
+                    }).count();
+
+                    if covered == 0 {
+                        continue;
+                    }
+
+                    if !self.depth_only {
+                        self.drawer.add_coverage(i, j, covered as f32 / Self::COVERAGE_SAMPLES as f32);
+                    }
+                }
+                else if !inside(&edge, 0) {
+                    continue;
+                }
+
+                let p = Vec3 { x: edge[0] as f32 / area, y: edge[1] as f32 / area, z: edge[2] as f32 / area };
+
+                self.shade_fragment(triangle, shader, &p, vertex_light, i, j);
+            }
+
+            for k in 0..3 {
+                column_start[k] += step_x[k];
+            }
+        }
+    }
+
+    fn rasterize_float(
+        &mut self,
+        triangle: &TriangleAttributes,
+        shader: &dyn Shader,
+        bbox: &BoundingBox,
+        vertex_light: &Option<[(f32, f32); 3]>
+    ) {
+        let [p1, p2, p3] = &triangle.screen;
+        let vertices = [
+            Vec2 { x: p1.x, y: p1.y },
+            Vec2 { x: p2.x, y: p2.y },
+            Vec2 { x: p3.x, y: p3.y }
+        ];
+
+        let area = Self::edge_function(&vertices[0], &vertices[1], &vertices[2]);
+        if area == 0.0 {
+            return;
+        }
+
+        // Edges are opposite to the vertices with the same index
+        let top_left = [
+            Self::is_top_left_edge(&vertices[1], &vertices[2], &vertices[0]),
+            Self::is_top_left_edge(&vertices[2], &vertices[0], &vertices[1]),
+            Self::is_top_left_edge(&vertices[0], &vertices[1], &vertices[2])
+        ];
+
+        // Pixels lying exactly on an edge are drawn only by the triangle to which
+        // the edge is a top or left one
+        let inside = |point: &Vec2| (0..3).all(|k| {
+            let edge = area.signum() * Self::edge_function(
+                &vertices[(k + 1) % 3], &vertices[(k + 2) % 3], point
+            );
+
+            edge > 0.0 || (edge == 0.0 && top_left[k])
+        });
+
+        // Height of a pixel in renderer coordinates
+        let pixel_height = self.to_renderer_coordinates(0, 0).y - self.to_renderer_coordinates(0, 1).y;
+
+        for i in bbox.min_x..=bbox.max_x {
+            for j in bbox.min_y..=bbox.max_y {
+                let point = self.to_renderer_coordinates(i, j);
+
+                if self.antialiasing {
+                    // Coverage is estimated with samples spread vertically across the pixel,
+                    // which smooths nearly horizontal edges
+                    let covered = (0..Self::COVERAGE_SAMPLES).filter(|&k| {
+                        let offset = (k as f32 + 0.5) / Self::COVERAGE_SAMPLES as f32 - 0.5;
+                        inside(&Vec2 { x: point.x, y: point.y + offset * pixel_height })
+                    }).count();
+
+                    if covered == 0 {
+                        continue;
+                    }
+
+                    if !self.depth_only {
+                        self.drawer.add_coverage(i, j, covered as f32 / Self::COVERAGE_SAMPLES as f32);
+                    }
+                }
+                else if !inside(&point) {
+                    continue;
+                }
+
+                let p = transform::to_barycentric(&vertices[0], &vertices[1], &vertices[2], &point);
+
+                self.shade_fragment(triangle, shader, &p, vertex_light, i, j);
+            }
+        }
+    }
+
+    // Depth tests the fragment with the given barycentric coordinates and shades it if visible
+    fn shade_fragment(
+        &mut self,
+        triangle: &TriangleAttributes,
+        shader: &dyn Shader,
+        p: &Vec3,
+        vertex_light: &Option<[(f32, f32); 3]>,
+        i: i32, j: i32
+    ) {
+        let [p1, p2, p3] = &triangle.screen;
+
+        // Depth is written before shading, as ambient occlusion reads it
+        let previous_depth = self.stored_depth(i, j);
+        if !self.update_zbuffer_and_check_if_visible(p, p1, p2, p3, i, j) || self.depth_only {
+            return;
+        }
+
+        let varyings = Varyings {
+            triangle,
+            position: p.x * triangle.world[0] + p.y * triangle.world[1] + p.z * triangle.world[2],
+            uv: p.x * triangle.uv[0] + p.y * triangle.uv[1] + p.z * triangle.uv[2],
+            normal: (
+                p.x * triangle.normals[0] + p.y * triangle.normals[1] + p.z * triangle.normals[2]
+            ).normalized(),
+            color: triangle.colors.as_ref().map(|colors| Self::interpolate_color(colors, p)),
+            direct_light: vertex_light.map(|[l1, l2, l3]| (
+                p.x * l1.0 + p.y * l2.0 + p.z * l3.0,
+                p.x * l1.1 + p.y * l2.1 + p.z * l3.1
+            )),
+            ambient_occlusion: triangle.ambient_occlusion.map(|[ao1, ao2, ao3]| p.x * ao1 + p.y * ao2 + p.z * ao3),
+            x: i,
+            y: j,
+            renderer: self
+        };
+
+        let color = match shader.fragment(*p, &varyings) {
+            Some(color) => self.apply_fog(&color, &varyings.position),
+            None => {
+                // Discarded fragments do not hide what is behind them
+                self.restore_depth(i, j, previous_depth);
+                return;
+            }
+        };
+
+        self.drawer.set_vertex(i, j, &color);
+        self.stats.fragments += 1;
+    }
+
+    fn interpolate_color(colors: &[Color; 3], p: &Vec3) -> Color {
+        let channel = |c1: u8, c2: u8, c3: u8| {
+            let channel = f32::from(c1) * p.x + f32::from(c2) * p.y + f32::from(c3) * p.z;
+            channel.round().clamp(0.0, 255.0) as u8
+        };
+
+        Color {
+            r: channel(colors[0].r, colors[1].r, colors[2].r),
+            g: channel(colors[0].g, colors[1].g, colors[2].g),
+            b: channel(colors[0].b, colors[1].b, colors[2].b)
+        }
+    }
+
+    // Depth of the pixel in the z-buffer and in the ambient occlusion buffer
+    fn stored_depth(&self, i: i32, j: i32) -> (f32, f32) {
+        let depth = self.zbuffer[(j * self.drawer.plane_size().width + i) as usize];
+
+        if self.ao_quality != AoQuality::Half {
+            return (depth, f32::NEG_INFINITY);
+        }
+
+        let scale = self.ao_quality.scale();
+        (depth, self.ao_buffer[((j / scale) * self.ao_buffer_size().width + i / scale) as usize])
+    }
+
+    fn restore_depth(&mut self, i: i32, j: i32, (depth, ao_depth): (f32, f32)) {
+        self.zbuffer[(j * self.drawer.plane_size().width + i) as usize] = depth;
+
+        if self.ao_quality == AoQuality::Half {
+            let scale = self.ao_quality.scale();
+            let ao_index = ((j / scale) * self.ao_buffer_size().width + i / scale) as usize;
+            self.ao_buffer[ao_index] = ao_depth;
+        }
+    }
+
+    fn update_zbuffer_and_check_if_visible(
+        &mut self,
+        p: &Vec3, p1: &Vec3, p2: &Vec3, p3: &Vec3,
+        i: i32, j: i32
+    ) -> bool {
+        let pixel_depth = p1.z * p.x + p2.z * p.y + p3.z * p.z;
+        let zbuffer_index = (j * self.drawer.plane_size().width + i) as usize;
+
+        let stored_depth = self.depth_mode.encode(pixel_depth);
+
+        // The prepass computes exactly the same depth for the nearest fragment
+        if self.depth_prepass && !self.depth_only {
+            return stored_depth == self.zbuffer[zbuffer_index];
+        }
+
+        if !self.depth_mode.is_nearer(stored_depth, self.zbuffer[zbuffer_index]) {
+            return false;
+        }
+
+        self.zbuffer[zbuffer_index] = stored_depth;
+
+        if self.ao_quality == AoQuality::Half {
+            let scale = self.ao_quality.scale();
+            let ao_index = ((j / scale) * self.ao_buffer_size().width + i / scale) as usize;
+            self.ao_buffer[ao_index] = self.ao_buffer[ao_index].max(pixel_depth);
+        }
+
+        true
+    }
+
+    pub fn calc_texture_coords(uv_coordinates: &Vec2, texture: &Image) -> (usize, usize) {
+        (
+            (uv_coordinates.x * (texture.size().width - 1) as f32) as usize,
+            (uv_coordinates.y * (texture.size().height - 1) as f32) as usize,
+        )
+    }
+
+    pub fn calc_normal_vector(
+        // Interpolated normal vector
+        n_vector: &Vec3,
+        // Vertices and UV coordinates of the triangle
+        vertices: &[Vec3; 3], uv: &[Vec2; 3],
+        // Interpolated UV coordinates of the fragment
+        fragment_uv: &Vec2, normal_map: &Image
+    ) -> Vec3 {
+        let [p1, p2, p3] = vertices;
+        let [t1, t2, t3] = uv;
+
+        // Tangent basis, the interpolated normal vector is used as is for degenerate triangles
+        let darboux_matrix = match Self::calc_darboux_matrix(p1, p2, p3, n_vector) {
+            Some(matrix) => matrix,
+            None => return *n_vector
+        };
+
+        let i_vector = darboux_matrix * Vec3 { x: t2.x - t1.x, y: t3.x - t1.x, z: 0.0 };
+        let j_vector = darboux_matrix * Vec3 { x: t2.y - t1.y, y: t3.y - t1.y, z: 0.0 };
+
+        // Collinear texture coordinates do not span the tangent plane
+        if i_vector.len() == 0.0 || j_vector.len() == 0.0 {
+            return *n_vector;
+        }
+
+        // The normal map may have a different size than the diffuse texture
+        let (x, y) = Self::calc_texture_coords(fragment_uv, normal_map);
+        let normal_color = normal_map.at(x, y);
+
+        (
+            (f32::from(normal_color.r) / 255.0).powi(3) * i_vector.normalized() +
+            (f32::from(normal_color.g) / 255.0).powi(3) * j_vector.normalized() +
+            (f32::from(normal_color.b) / 255.0).powi(3) * *n_vector
+        ).normalized()
+    }
+
+    fn calc_darboux_matrix(p1: &Vec3, p2: &Vec3, p3: &Vec3, n_vector: &Vec3) -> Option<Matrix3> {
+        Matrix3::new([
+            [p2.x - p1.x, p2.y - p1.y, p2.z - p1.z],
+            [p3.x - p1.x, p3.y - p1.y, p3.z - p1.z],
+            [n_vector.x, n_vector.y, n_vector.z]
+        ]).inverse()
+    }
+
+    pub fn calc_shadow_light(&self, p: &Vec3, s1: &Vec3, s2: &Vec3, s3: &Vec3) -> f32 {
+        if !self.has_shadow_light || !self.shadows {
+            return 0.0;
+        }
+
+        let shadow_vector = p.x * *s1 + p.y * *s2 + p.z * *s3;
+
+        let shadow_coordinates = self.to_shadow_coordinates(
+            Vec2 { x: shadow_vector.x, y: shadow_vector.y }
+        );
+
+        match self.shadow_filter {
+            ShadowFilter::Hard => {
+                if self.in_shadow(shadow_coordinates.0, shadow_coordinates.1, shadow_vector.z) {
+                    -1.0
+                }
+                else {
+                    0.0
+                }
+            },
+            ShadowFilter::Pcf(size) => {
+                let shadow_size = self.shadow_size();
+                let mut shadowed_samples = 0;
+
+                for i in 0..size {
+                    for j in 0..size {
+                        // Samples outside of the shadow buffer are moved to its border
+                        let x = shadow_coordinates.0 + i - (size - 1) / 2;
+                        let y = shadow_coordinates.1 + j - (size - 1) / 2;
+                        let x = std::cmp::min(std::cmp::max(x, 0), shadow_size.width - 1);
+                        let y = std::cmp::min(std::cmp::max(y, 0), shadow_size.height - 1);
+
+                        if self.in_shadow(x, y, shadow_vector.z) {
+                            shadowed_samples += 1;
+                        }
+                    }
+                }
+
+                -(shadowed_samples as f32) / (size * size) as f32
+            }
+        }
+    }
+
+    // Fragments outside of the light's view are never in shadow
+    fn in_shadow(&self, x: i32, y: i32, depth: f32) -> bool {
+        let shadow_size = self.shadow_size();
+        if x < 0 || x >= shadow_size.width || y < 0 || y >= shadow_size.height {
+            return false;
+        }
+
+        let shadow_buffer_index = (y * shadow_size.width + x) as usize;
+        self.shadow_buffer[shadow_buffer_index] > depth + 0.2
+    }
+
+    pub fn set_shadows(&mut self, shadows: bool) {
+        self.shadows = shadows;
+    }
+
+    // For scenes in which only the camera moves. Changing the shadow casting light invalidates
+    // the shadow buffer by itself, moved or changed geometry has to be marked with invalidate_shadows
+    pub fn set_static_shadows(&mut self, static_shadows: bool) {
+        self.static_shadows = static_shadows;
+        self.invalidate_shadows();
+    }
+
+    // Clears the shadow buffer, which is filled in again by the models drawn afterwards,
+    // so it should be called before the first model of the frame in which the geometry changes
+    pub fn invalidate_shadows(&mut self) {
+        self.shadow_buffer = Self::create_zbuffer(self.shadow_size(), DepthMode::Standard);
+        self.shadow_map_filled = false;
+        self.shadow_map_cached = false;
+    }
+
+    // Once enabled, only fragments at exactly the depth written by the prepass are drawn. Everything
+    // drawn with a 3D primitive has to go through depth_prepass_with_shader first, with a shader
+    // which moves its vertices the same way, otherwise it is hidden. Markers are not affected
+    pub fn set_depth_prepass(&mut self, depth_prepass: bool) {
+        self.depth_prepass = depth_prepass;
+    }
+
+    pub fn set_shadow_filter(&mut self, shadow_filter: ShadowFilter) {
+        self.shadow_filter = shadow_filter;
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: ToneMap) {
+        self.tonemap = tonemap;
+    }
+
+    // Quantizes the diffuse light into the given number of bands, at least two
+    pub fn set_toon_bands(&mut self, toon_bands: Option<u32>) {
+        self.toon_bands = toon_bands.map(|bands| bands.max(2));
+    }
+
+    // Lights the color with the given intensity, which may be greater than 1
+    pub fn apply_light(&self, color: &Color, light_intensity: f32) -> Color {
+        match self.tonemap {
+            ToneMap::None => *color * light_intensity,
+            tonemap => color.map_linear(|channel| tonemap.map(channel * light_intensity))
+        }
+    }
+
+    pub fn calc_light_intensity(
+        &self,
+        normal_vector: &Vec3,
+        position: &Vec3,
+        shadow_light: f32,
+        // Scales the specular light of this fragment
+        specular_factor: f32,
+        shininess: f32,
+        ambient_light: f32
+    ) -> f32 {
+        let direct_light = self.calc_direct_light(normal_vector, position, shininess);
+        self.combine_light(direct_light, shadow_light, specular_factor, ambient_light)
+    }
+
+    // Baked ambient occlusion of the fragment if the mesh has it, otherwise the screen space one
+    pub fn ambient_light(&self, varyings: &Varyings) -> f32 {
+        match varyings.ambient_occlusion {
+            Some(ambient_occlusion) => ambient_occlusion,
+            None => self.ambient_occlusion(varyings.x, varyings.y)
+        }
+    }
+
+    // Adds ambient and shadow light to the diffuse and specular light of the lights
+    pub fn combine_light(
+        &self,
+        (diffuse_light, specular_light): (f32, f32),
+        shadow_light: f32,
+        specular_factor: f32,
+        ambient_light: f32
+    ) -> f32 {
+        let light_intensity =
+            ambient_light * 0.4 +
+            shadow_light * 0.2 +
+            specular_light * 0.7 * specular_factor +
+            diffuse_light * 1.0;
+
+        light_intensity.max(0.0)
+    }
+
+    // Returns diffuse and specular light summed over all lights
+    pub fn calc_direct_light(&self, normal_vector: &Vec3, position: &Vec3, shininess: f32) -> (f32, f32) {
+        let mut diffuse_sum = 0.0;
+        let mut specular_sum = 0.0;
+
+        for light in self.lights.iter() {
+            let (light_direction, attenuation) = light.incidence(position);
+            let (diffuse_light, specular_light) = self.calc_reflected_light(
+                &self.transform_normal(&light_direction), normal_vector, shininess
+            );
+
+            let diffuse_light = match self.toon_bands {
+                Some(bands) => {
+                    let bands = bands as f32;
+                    (diffuse_light.max(0.0) * bands).floor().min(bands - 1.0) / (bands - 1.0)
+                },
+                None => diffuse_light
+            };
+
+            // Surfaces facing away from a light are not darkened by it
+            diffuse_sum += attenuation * diffuse_light.max(0.0);
+            specular_sum += attenuation * specular_light.max(0.0);
+        }
+
+        (diffuse_sum, specular_sum)
+    }
+
+    // Returns diffuse and specular light
+    fn calc_reflected_light(&self, light_vector: &Vec3, normal_vector: &Vec3, shininess: f32) -> (f32, f32) {
+        let view_vector = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+
+        let specular_light = match self.specular_model {
+            SpecularModel::Phong => {
+                let reflection_vector = 2.0 * light_vector.project_onto(normal_vector) - *light_vector;
+
+                (reflection_vector * view_vector).max(0.0).powf(shininess)
+            },
+            SpecularModel::BlinnPhong => {
+                let halfway_vector = (*light_vector + view_vector).normalized();
+
+                (*normal_vector * halfway_vector).max(0.0).powf(shininess)
+            }
+        };
+
+        let diffuse_light = *normal_vector * *light_vector;
+
+        (diffuse_light, specular_light)
+    }
+
+    // Fills in only the z-buffer, all models have to go through it before any is drawn
+    // with the depth prepass enabled. Fragments discarded by the shader still hide what is behind them.
+    pub fn depth_prepass(&mut self, mesh: &Mesh, pos: &Vec3) {
+        self.depth_prepass_with_shader(mesh, &DepthOnlyShader, pos);
+    }
+
+    // For models drawn with a shader which moves the vertices, only its vertex function is used
+    pub fn depth_prepass_with_shader(&mut self, mesh: &Mesh, shader: &dyn Shader, pos: &Vec3) {
+        self.depth_only = true;
+        self.model_with_shader(mesh, shader, pos);
+        self.depth_only = false;
+    }
+
+    pub fn model(&mut self, mesh: &Mesh, material: &Material, pos: &Vec3) {
+        self.model_with_shader(mesh, &PhongShader::new(material), pos);
+    }
+
+    pub fn model_with_shader(&mut self, mesh: &Mesh, shader: &dyn Shader, pos: &Vec3) {
+        self.faces_with_shader(mesh, shader, pos, 0..mesh.face_count());
+    }
+
+    // Draws only the faces with indices in the range, e.g. to draw a large mesh a chunk per frame.
+    // Indices past the last face are ignored
+    pub fn model_range(&mut self, mesh: &Mesh, material: &Material, pos: &Vec3, faces: Range<usize>) {
+        self.faces_with_shader(mesh, &PhongShader::new(material), pos, faces);
+    }
+
+    fn faces_with_shader(&mut self, mesh: &Mesh, shader: &dyn Shader, pos: &Vec3, faces: Range<usize>) {
+        // Vertices are shared by several faces, so they are transformed only once.
+        // Only the ones used by the faces in the range are transformed at all
+        let mut vertices: Vec<Option<ProjectedVertex>> = vec![None; mesh.vertex_count()];
+        let mut normals: Vec<Option<Vec3>> = vec![None; mesh.normal_count()];
+        for face in mesh.faces().skip(faces.start).take(faces.len()) {
+            for (&vertex, &normal) in face.vertices.iter().zip(face.normals.iter()) {
+                vertices[vertex].get_or_insert_with(|| {
+                    self.project_vertex(&shader.vertex(&(*mesh.vertex(vertex) + *pos)))
+                });
+                normals[normal].get_or_insert_with(|| self.transform_normal(mesh.normal(normal)));
+            }
+        }
+
+        self.mesh_faces(mesh, faces, &vertices, &normals, shader);
+    }
+
+    // Draws the mesh once for every model matrix, which transforms it like Mesh::transform
+    pub fn model_instanced(&mut self, mesh: &Mesh, material: &Material, transforms: &[Matrix4]) {
+        let shader = PhongShader::new(material);
+
+        for transform in transforms {
+            // Normal vectors go from model to view space with a single matrix
+            let normal_matrix = self.normal_projection_matrix * self.view_matrix * *transform;
+
+            let vertices: Vec<Option<ProjectedVertex>> = (0..mesh.vertex_count())
+                .map(|i| {
+                    let position = (*transform * mesh.vertex(i).homo_point()).point_proj();
+                    Some(self.project_vertex(&shader.vertex(&position)))
+                })
+                .collect();
+            let normals: Vec<Option<Vec3>> = (0..mesh.normal_count())
+                .map(|i| Some((normal_matrix * mesh.normal(i).homo_vector()).vector_proj()))
+                .collect();
+
+            self.mesh_faces(mesh, 0..mesh.face_count(), &vertices, &normals, &shader);
+        }
+    }
+
+    // Vertices and normal vectors are already transformed, at least the ones used by the faces in the range
+    fn mesh_faces(
+        &mut self,
+        mesh: &Mesh,
+        faces: Range<usize>,
+        vertices: &[Option<ProjectedVertex>],
+        normals: &[Option<Vec3>],
+        shader: &dyn Shader
+    ) {
+        let vertex = |i: usize| vertices[i].as_ref().expect("vertex of a face in the range is transformed");
+        let normal = |i: usize| normals[i].expect("normal vector of a face in the range is transformed");
+
+        for face in mesh.faces().skip(faces.start).take(faces.len()) {
+            let colors = if mesh.has_vertex_colors() {
+                Some([
+                    *mesh.vertex_color(face.vertices[0]),
+                    *mesh.vertex_color(face.vertices[1]),
+                    *mesh.vertex_color(face.vertices[2])
+                ])
+            }
+            else {
+                None
+            };
+
+            let ambient_occlusion = if mesh.has_vertex_ao() {
+                Some([
+                    mesh.vertex_ao(face.vertices[0]),
+                    mesh.vertex_ao(face.vertices[1]),
+                    mesh.vertex_ao(face.vertices[2])
+                ])
+            }
+            else {
+                None
+            };
+
+            self.projected_triangle(
+                [
+                    vertex(face.vertices[0]),
+                    vertex(face.vertices[1]),
+                    vertex(face.vertices[2])
+                ],
+                [
+                    mesh.texture_coord(face.texture_coords[0]),
+                    mesh.texture_coord(face.texture_coords[1]),
+                    mesh.texture_coord(face.texture_coords[2])
+                ],
+                [
+                    normal(face.normals[0]),
+                    normal(face.normals[1]),
+                    normal(face.normals[2])
+                ],
+                colors.as_ref(),
+                ambient_occlusion,
+                shader
+            );
+        }
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headless(cols: i32, rows: i32) -> Renderer {
+        Renderer::with_drawer(Drawer::with_writer(std::io::sink(), WinSize { cols, rows }))
+    }
+
+    fn vec3(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    // Fragments drawn with a single color, independently of the light
+    struct SolidShader(Color);
+
+    impl Shader for SolidShader {
+        fn fragment(&self, _bary: Vec3, _varyings: &Varyings) -> Option<Color> {
+            Some(self.0)
+        }
+    }
+
+    fn draw_triangle(renderer: &mut Renderer, positions: [Vec3; 3], shader: &dyn Shader) {
+        let vertex = |position| Vertex { position, uv: Vec2 { x: 0.0, y: 0.0 }, normal: vec3(0.0, 0.0, 1.0) };
+        renderer.triangle(&[vertex(positions[0]), vertex(positions[1]), vertex(positions[2])], shader);
+    }
+
+    // Color of the pixel of a saved frame, with row 0 at the top like in the drawing plane
+    fn pixel(frame: &Image, x: i32, y: i32) -> Color {
+        *frame.at(x as usize, (frame.size().height - 1 - y) as usize)
+    }
+
+    // Unit cube with normals pointing out of the corners, which are smooth only across faces
+    const CUBE_OBJ: &str = "\
+        v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+        v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+        vt 0 0\n\
+        vn -1 -1 1\nvn 1 -1 1\nvn 1 1 1\nvn -1 1 1\n\
+        vn -1 -1 -1\nvn 1 -1 -1\nvn 1 1 -1\nvn -1 1 -1\n\
+        f 1/1/1 2/1/2 3/1/3\nf 1/1/1 3/1/3 4/1/4\n\
+        f 6/1/6 5/1/5 8/1/8\nf 6/1/6 8/1/8 7/1/7\n\
+        f 2/1/2 6/1/6 7/1/7\nf 2/1/2 7/1/7 3/1/3\n\
+        f 5/1/5 1/1/1 4/1/4\nf 5/1/5 4/1/4 8/1/8\n\
+        f 4/1/4 3/1/3 7/1/7\nf 4/1/4 7/1/7 8/1/8\n\
+        f 5/1/5 6/1/6 2/1/2\nf 5/1/5 2/1/2 1/1/1\n";
+
+    fn drawn_pixels(renderer: &Renderer) -> usize {
+        let clear_value = renderer.depth_mode.clear_value();
+        renderer.zbuffer().iter().filter(|&&depth| depth != clear_value).count()
+    }
+
+    #[test]
+    fn clips_triangle_crossing_near_plane() {
+        let mut renderer = headless(80, 40);
+        renderer.camera(&vec3(0.0, 0.0, 1.0), &Vec3::ZERO, &vec3(0.0, 1.0, 0.0));
+
+        let vertex = |position| ClipVertex {
+            position,
+            uv: Vec2 { x: 0.0, y: 0.0 },
+            normal: vec3(0.0, 1.0, 0.0),
+            color: Color::WHITE,
+            ambient_occlusion: 1.0
+        };
+
+        // A floor triangle with one vertex far behind the camera is cut into a quad
+        let polygon = renderer.clip_near_plane([
+            vertex(vec3(-1.0, -0.5, 0.0)),
+            vertex(vec3(1.0, -0.5, 0.0)),
+            vertex(vec3(0.0, -0.5, 50.0))
+        ]);
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|v| renderer.clip_w(&v.position) >= Renderer::NEAR_PLANE_W - 1e-4));
+
+        renderer.refresh_with(&Color::BLACK);
+        draw_triangle(
+            &mut renderer,
+            [vec3(-1.0, -0.5, 0.0), vec3(1.0, -0.5, 0.0), vec3(0.0, -0.5, 50.0)],
+            &SolidShader(Color::WHITE)
+        );
+
+        // The visible part is drawn and no fragment got a broken depth
+        assert!(drawn_pixels(&renderer) > 0);
+        assert!(renderer.zbuffer().iter().all(|depth| !depth.is_nan()));
+    }
+
+    #[test]
+    fn nearer_depth_is_brighter() {
+        let mut renderer = headless(4, 2);
+        renderer.zbuffer[0] = 0.75;
+        renderer.zbuffer[1] = 0.5;
+        renderer.zbuffer[2] = 0.25;
+        renderer.render_depth();
+
+        let frame = renderer.drawer.to_image();
+        let (near, middle, far) = (pixel(&frame, 0, 0), pixel(&frame, 1, 0), pixel(&frame, 2, 0));
+        assert_eq!((near.r, far.r), (255, 0));
+        assert!(middle.r > far.r && middle.r < near.r);
+        // Empty pixels stay black
+        assert_eq!(pixel(&frame, 3, 0).r, 0);
+    }
+
+    #[test]
+    fn close_point_light_is_brighter() {
+        let normal = vec3(0.0, 0.0, 1.0);
+        let diffuse_light = |distance: f32| {
+            let mut renderer = headless(4, 2);
+            renderer.clear_lights();
+            renderer.add_light(Light::Point {
+                position: vec3(0.0, 0.0, distance),
+                constant: 1.0,
+                linear: 0.0,
+                quadratic: 0.5
+            });
+
+            renderer.calc_direct_light(&normal, &Vec3::ZERO, Material::DEFAULT_SHININESS).0
+        };
+
+        assert!(diffuse_light(2.0) > diffuse_light(20.0));
+    }
+
+    #[test]
+    fn opposing_lights_light_sphere_evenly() {
+        let mut renderer = headless(4, 2);
+        renderer.clear_lights();
+        renderer.add_light(Light::Directional { dir: vec3(1.0, 0.0, 0.0) });
+        renderer.add_light(Light::Directional { dir: vec3(-1.0, 0.0, 0.0) });
+
+        // Points of a unit sphere mirrored across the plane between the lights
+        for angle in [0.0f32, 0.4, 0.8, 1.2].iter() {
+            let right = vec3(angle.cos(), angle.sin(), 0.0);
+            let left = vec3(-angle.cos(), angle.sin(), 0.0);
+
+            let (right_light, _) = renderer.calc_direct_light(&right, &right, Material::DEFAULT_SHININESS);
+            let (left_light, _) = renderer.calc_direct_light(&left, &left, Material::DEFAULT_SHININESS);
+
+            assert!(right_light > 0.0);
+            assert!((right_light - left_light).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn fog_covers_distant_fragments() {
+        let mut renderer = headless(4, 2);
+        renderer.set_fog(Color::WHITE, 2.0, 10.0);
+        let color = Color { r: 200, g: 0, b: 0 };
+
+        // The identity view matrix looks in the -z direction from the origin
+        let near = renderer.apply_fog(&color, &vec3(0.0, 0.0, -1.0));
+        let far = renderer.apply_fog(&color, &vec3(0.0, 0.0, -20.0));
+
+        assert_eq!((near.r, near.g, near.b), (200, 0, 0));
+        assert_eq!((far.r, far.g, far.b), (255, 255, 255));
+    }
+
+    #[test]
+    fn custom_shader_colors_fragments() {
+        let mut renderer = headless(20, 10);
+        renderer.refresh_with(&Color::BLACK);
+        draw_triangle(
+            &mut renderer,
+            [vec3(-0.5, -0.5, 0.0), vec3(0.5, -0.5, 0.0), vec3(0.0, 0.5, 0.0)],
+            &SolidShader(Color::GREEN)
+        );
+
+        let center = pixel(&renderer.drawer.to_image(), 10, 10);
+        assert_eq!((center.r, center.g, center.b), (0, 255, 0));
+    }
+
+    // Records the diffuse light of the fragments on the z = 1 face of the cube
+    struct FrontFaceLight(std::cell::RefCell<Vec<f32>>);
+
+    impl Shader for FrontFaceLight {
+        fn fragment(&self, _bary: Vec3, varyings: &Varyings) -> Option<Color> {
+            if varyings.triangle.world.iter().all(|vertex| vertex.z == 1.0) {
+                let (diffuse_light, _) = varyings.renderer.calc_direct_light(
+                    &varyings.normal, &varyings.position, Material::DEFAULT_SHININESS
+                );
+                self.0.borrow_mut().push(diffuse_light);
+            }
+
+            Some(Color::WHITE)
+        }
+    }
+
+    #[test]
+    fn flat_shading_lights_faces_uniformly() {
+        let cube = Mesh::from_obj_str(CUBE_OBJ).unwrap();
+        let front_face_light = |shading_mode| {
+            let mut renderer = headless(80, 40);
+            renderer.set_camera(&Camera::orbit(Vec3::ZERO, 5.0, 0.5, 0.4));
+            renderer.light(&vec3(0.3, 0.5, 1.0).normalized());
+            renderer.set_shading_mode(shading_mode);
+            renderer.refresh_with(&Color::BLACK);
+
+            let probe = FrontFaceLight(std::cell::RefCell::new(Vec::new()));
+            renderer.model_with_shader(&cube, &probe, &Vec3::ZERO);
+            probe.0.into_inner()
+        };
+
+        let flat = front_face_light(ShadingMode::Flat);
+        assert!(flat.len() > 10);
+        assert!(flat.iter().all(|light| (light - flat[0]).abs() < 1e-5));
+
+        // Interpolated corner normals light the same face unevenly
+        let smooth = front_face_light(ShadingMode::Smooth);
+        assert!(smooth.iter().any(|light| (light - smooth[0]).abs() > 1e-2));
+    }
+
+    #[test]
+    fn blinn_phong_keeps_grazing_highlights() {
+        let mut renderer = headless(4, 2);
+        let normal = vec3(0.0, 0.0, 1.0);
+        // Light almost parallel to the surface, seen from above
+        let light = vec3(1.0, 0.0, 0.1).normalized();
+
+        let (_, phong) = renderer.calc_reflected_light(&light, &normal, 16.0);
+        renderer.set_specular_model(SpecularModel::BlinnPhong);
+        let (_, blinn_phong) = renderer.calc_reflected_light(&light, &normal, 16.0);
+
+        assert!(phong < 1e-6);
+        assert!(blinn_phong > 1e-3);
+    }
+
+    #[test]
+    fn half_resolution_ao_is_close_on_flat_surface() {
+        let floor_ao = |ao_quality| {
+            let mut renderer = headless(80, 40);
+            renderer.set_camera(&Camera::orbit(Vec3::ZERO, 3.0, 0.0, 0.6));
+            renderer.set_ao_quality(ao_quality);
+            renderer.refresh_with(&Color::BLACK);
+
+            let floor = [vec3(-4.0, 0.0, 4.0), vec3(4.0, 0.0, 4.0), vec3(4.0, 0.0, -4.0), vec3(-4.0, 0.0, -4.0)];
+            draw_triangle(&mut renderer, [floor[0], floor[1], floor[2]], &SolidShader(Color::WHITE));
+            draw_triangle(&mut renderer, [floor[0], floor[2], floor[3]], &SolidShader(Color::WHITE));
+
+            // Only the middle of the floor, the rays from its borders see different edges
+            (30..50).flat_map(|y| (30..50).map(move |x| (x, y)))
+                .map(|(x, y)| renderer.ambient_occlusion(x, y))
+                .collect::<Vec<f32>>()
+        };
+
+        let full = floor_ao(AoQuality::Full);
+        let half = floor_ao(AoQuality::Half);
+        let max_difference = full.iter().zip(half.iter()).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        assert!(max_difference < 0.05);
+    }
+
+    #[test]
+    fn counts_fragments_of_triangle() {
+        let mut renderer = headless(40, 20);
+        renderer.refresh_with(&Color::BLACK);
+        draw_triangle(
+            &mut renderer,
+            [vec3(-0.5, -0.5, 0.0), vec3(0.5, -0.5, 0.0), vec3(-0.5, 0.5, 0.0)],
+            &SolidShader(Color::WHITE)
+        );
+
+        let stats = renderer.stats();
+        assert_eq!((stats.triangles, stats.triangles_culled), (1, 0));
+        assert_eq!(stats.fragments, drawn_pixels(&renderer));
+
+        // A right triangle covers about half of its bounding box
+        let frame = renderer.drawer.to_image();
+        let width = (0..40).filter(|&x| (0..40).any(|y| pixel(&frame, x, y).r != 0)).count();
+        let height = (0..40).filter(|&y| (0..40).any(|x| pixel(&frame, x, y).r != 0)).count();
+        let half_box = (width * height) as f32 / 2.0;
+        assert!((stats.fragments as f32 - half_box).abs() < (width + height) as f32);
+    }
+
+    // Counts the fragments shaded at every pixel
+    struct FragmentCounter(std::cell::RefCell<std::collections::HashMap<(i32, i32), u32>>);
+
+    impl Shader for FragmentCounter {
+        fn fragment(&self, _bary: Vec3, varyings: &Varyings) -> Option<Color> {
+            *self.0.borrow_mut().entry((varyings.x, varyings.y)).or_insert(0) += 1;
+            Some(Color::WHITE)
+        }
+    }
+
+    #[test]
+    fn shared_edge_is_drawn_once() {
+        for &rasterizer in [Rasterizer::Float, Rasterizer::FixedPoint].iter() {
+            let mut renderer = headless(80, 40);
+            renderer.set_rasterizer(rasterizer);
+            renderer.refresh_with(&Color::BLACK);
+
+            let counter = FragmentCounter(std::cell::RefCell::new(std::collections::HashMap::new()));
+            let quad = [vec3(-0.6, -0.4, 0.0), vec3(0.5, -0.5, 0.0), vec3(0.6, 0.45, 0.0), vec3(-0.4, 0.5, 0.0)];
+            draw_triangle(&mut renderer, [quad[0], quad[1], quad[2]], &counter);
+            // Without clearing the z-buffer pixels drawn twice would be hidden by the depth test
+            renderer.refresh_with(&Color::BLACK);
+            draw_triangle(&mut renderer, [quad[0], quad[2], quad[3]], &counter);
+
+            let fragments = counter.0.into_inner();
+            assert!(fragments.values().all(|&count| count == 1));
+
+            // The quad is convex, so there are no gaps in its rows
+            for y in 0..80 {
+                let row: Vec<i32> = (0..80).filter(|&x| fragments.contains_key(&(x, y))).collect();
+                if let (Some(first), Some(last)) = (row.first(), row.last()) {
+                    assert_eq!(row.len() as i32, last - first + 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn disk_comes_out_round() {
+        for &pixel_aspect in [1.0f32, 0.5].iter() {
+            let mut renderer = headless(160, 60);
+            renderer.set_pixel_aspect(pixel_aspect);
+            renderer.refresh_with(&Color::BLACK);
+
+            let counter = FragmentCounter(std::cell::RefCell::new(std::collections::HashMap::new()));
+            let rim = |k: i32| {
+                let angle = k as f32 / 64.0 * 2.0 * std::f32::consts::PI;
+                vec3(0.8 * angle.cos(), 0.8 * angle.sin(), 0.0)
+            };
+            for k in 0..64 {
+                draw_triangle(&mut renderer, [Vec3::ZERO, rim(k), rim(k + 1)], &counter);
+            }
+
+            let fragments = counter.0.into_inner();
+            let (xs, ys): (Vec<i32>, Vec<i32>) = fragments.keys().cloned().unzip();
+            // Widths are measured in the units of pixel heights
+            let width = (xs.iter().max().unwrap() - xs.iter().min().unwrap() + 1) as f32 * pixel_aspect;
+            let height = (ys.iter().max().unwrap() - ys.iter().min().unwrap() + 1) as f32;
+            assert!((width - height).abs() <= 2.0);
+        }
+    }
+
+    #[test]
+    fn culls_triangle_outside_frustum() {
+        let mut renderer = headless(40, 20);
+        renderer.refresh_with(&Color::BLACK);
+        draw_triangle(
+            &mut renderer,
+            [vec3(5.0, 0.0, 0.0), vec3(6.0, 0.0, 0.0), vec3(5.0, 1.0, 0.0)],
+            &SolidShader(Color::WHITE)
+        );
+
+        let stats = renderer.stats();
+        assert_eq!(stats.fragments, 0);
+        assert_eq!(stats.triangles_frustum_culled, 1);
+        assert_eq!(drawn_pixels(&renderer), 0);
+    }
+
+    #[test]
+    fn antialiasing_blends_shallow_edge() {
+        let blended_pixels = |antialiasing| {
+            let mut renderer = headless(80, 30);
+            renderer.set_antialiasing(antialiasing);
+            renderer.refresh_with(&Color::BLACK);
+            draw_triangle(
+                &mut renderer,
+                [vec3(-0.9, -0.5, 0.0), vec3(0.9, -0.5, 0.0), vec3(0.9, -0.3, 0.0)],
+                &SolidShader(Color::WHITE)
+            );
+
+            let frame = renderer.drawer.to_image();
+            (0..60).flat_map(|y| (0..80).map(move |x| (x, y)))
+                .filter(|&(x, y)| {
+                    let color = pixel(&frame, x, y);
+                    color.r > 0 && color.r < 255
+                })
+                .count()
+        };
+
+        assert_eq!(blended_pixels(false), 0);
+        assert!(blended_pixels(true) > 10);
+    }
+
+    #[test]
+    fn pcf_softens_shadow_boundary() {
+        let shadow_light = |shadow_filter| {
+            let mut renderer = headless(4, 2);
+            renderer.set_shadows(true);
+            renderer.set_shadow_filter(shadow_filter);
+            renderer.set_shadow_resolution(16, 16);
+
+            // The left half of the shadow map is covered by an occluder close to the light
+            for y in 0..16 {
+                for x in 0..8 {
+                    renderer.shadow_buffer[y * 16 + x] = 1.0;
+                }
+            }
+
+            // Every fragment in the middle of the map, just on the lit side of the boundary
+            (0..4).map(|i| {
+                let point = vec3(i as f32 * 0.125 - 0.125, 0.0, 0.0);
+                renderer.calc_shadow_light(&vec3(1.0, 0.0, 0.0), &point, &point, &point)
+            }).collect::<Vec<f32>>()
+        };
+
+        let hard = shadow_light(ShadowFilter::Hard);
+        assert!(hard.contains(&-1.0) && hard.contains(&0.0));
+        assert!(hard.iter().all(|&light| light == 0.0 || light == -1.0));
+        let pcf = shadow_light(ShadowFilter::Pcf(3));
+        assert!(pcf.iter().any(|&light| light > -1.0 && light < 0.0));
+    }
+
+    #[test]
+    fn shadow_resolution_is_independent_of_display() {
+        let mut renderer = headless(40, 20);
+        assert_eq!(renderer.shadow_buffer().len(), 40 * 40);
+
+        renderer.set_shadow_resolution(128, 64);
+        assert_eq!(renderer.shadow_buffer().len(), 128 * 64);
+        assert_eq!(renderer.zbuffer().len(), 40 * 40);
+        assert_eq!(renderer.drawer.plane_size().width * renderer.drawer.plane_size().height, 40 * 40);
+    }
+
+    #[test]
+    fn shadow_outside_frustum_is_lit() {
+        let mut renderer = headless(40, 20);
+        renderer.set_shadows(true);
+        let inside = vec3(0.1, 0.1, 0.0);
+        renderer.fill_in_shadow_buffer(&vec3(-1.0, -1.0, 0.5), &vec3(1.0, -1.0, 0.5), &vec3(0.0, 1.0, 0.5));
+
+        let bary = vec3(1.0, 0.0, 0.0);
+        assert_eq!(renderer.calc_shadow_light(&bary, &inside, &inside, &inside), -1.0);
+        for far in [vec3(5.0, 0.0, 0.0), vec3(-5.0, -5.0, 0.0), vec3(0.0, 9.0, 0.0), vec3(1e9, -1e9, 0.0)].iter() {
+            assert_eq!(renderer.calc_shadow_light(&bary, far, far, far), 0.0);
+        }
+    }
+
+    #[test]
+    fn both_depth_modes_keep_front_fragment() {
+        let near = [vec3(-0.5, -0.5, 0.2), vec3(0.5, -0.5, 0.2), vec3(0.0, 0.5, 0.2)];
+        let far = [vec3(-0.5, -0.5, -0.2), vec3(0.5, -0.5, -0.2), vec3(0.0, 0.5, -0.2)];
+
+        for &depth_mode in [DepthMode::Standard, DepthMode::Reversed].iter() {
+            for &near_first in [true, false].iter() {
+                let mut renderer = headless(20, 10);
+                renderer.set_depth_mode(depth_mode);
+                renderer.refresh_with(&Color::BLACK);
+
+                let mut order = [(near, Color::GREEN), (far, Color::RED)];
+                if !near_first {
+                    order.reverse();
+                }
+                for (vertices, color) in order.iter() {
+                    draw_triangle(&mut renderer, *vertices, &SolidShader(*color));
+                }
+
+                let center = pixel(&renderer.drawer.to_image(), 10, 10);
+                assert_eq!((center.r, center.g, center.b), (0, 255, 0));
+            }
+        }
+    }
+
+    // Keeps the interpolated vertex color of the fragment nearest to the centroid of the triangle
+    struct CentroidColor(std::cell::RefCell<Option<(f32, Color)>>);
+
+    impl Shader for CentroidColor {
+        fn fragment(&self, bary: Vec3, varyings: &Varyings) -> Option<Color> {
+            let third = 1.0 / 3.0;
+            let distance = (bary.x - third).abs() + (bary.y - third).abs() + (bary.z - third).abs();
+            let color = varyings.color?;
+            let mut nearest = self.0.borrow_mut();
+            if nearest.is_none_or(|(nearest_distance, _)| distance < nearest_distance) {
+                *nearest = Some((distance, color));
+            }
+            Some(color)
+        }
+    }
+
+    #[test]
+    fn centroid_of_colored_triangle_has_average_color() {
+        let mesh = Mesh::from_obj_str("\
+            v -0.5 -0.5 0 1 0 0\nv 0.5 -0.5 0 0 1 0\nv 0 0.5 0 0 0 1\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\n").unwrap();
+        let mut renderer = headless(80, 40);
+        renderer.refresh_with(&Color::BLACK);
+
+        let shader = CentroidColor(std::cell::RefCell::new(None));
+        renderer.model_with_shader(&mesh, &shader, &Vec3::ZERO);
+
+        let (distance, color) = shader.0.into_inner().unwrap();
+        assert!(distance < 0.1);
+        for &channel in [color.r, color.g, color.b].iter() {
+            assert!((i32::from(channel) - 85).abs() <= 8);
+        }
+    }
+
+    // Quad facing the camera with UV coordinates from 0 to 1
+    const QUAD_OBJ: &str = "\
+        v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\n\
+        vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n\
+        f 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\n";
+
+    #[test]
+    fn black_specular_map_removes_highlights() {
+        let mesh = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+        let gray = Color { r: 60, g: 60, b: 60 };
+        let diffuse = Image::from_colors(vec![gray], Size { width: 1, height: 1 });
+        // Black on the left half of the quad and white on the right one
+        let specular_map = Image::from_colors(
+            vec![Color::BLACK, Color::WHITE, Color::WHITE], Size { width: 3, height: 1 }
+        );
+
+        let render = |material: &Material| {
+            let mut renderer = headless(80, 40);
+            renderer.light(&vec3(0.0, 0.0, 1.0));
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(&mesh, material, &Vec3::ZERO);
+            renderer.drawer.to_image()
+        };
+
+        let plain = render(&Material::new(diffuse.clone()));
+        let mapped = render(&Material::new(diffuse.clone()).with_specular_map(specular_map));
+        let (left, right) = (pixel(&mapped, 30, 20), pixel(&mapped, 50, 20));
+
+        assert!(left.r < right.r);
+        assert_eq!(right.r, pixel(&plain, 50, 20).r);
+    }
+
+    #[test]
+    fn renders_material_with_only_diffuse_map() {
+        let mesh = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+        let red = Color { r: 200, g: 0, b: 0 };
+        let material = Material::new(Image::from_colors(vec![red], Size { width: 1, height: 1 }));
+
+        let mut renderer = headless(40, 20);
+        renderer.refresh_with(&Color::BLACK);
+        renderer.model(&mesh, &material, &Vec3::ZERO);
+
+        let center = pixel(&renderer.drawer.to_image(), 20, 10);
+        assert!(center.r > 0);
+        assert_eq!((center.g, center.b), (0, 0));
+    }
+
+    #[test]
+    fn normal_map_is_sampled_at_its_own_size() {
+        let mesh = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+        let diffuse = Image::from_colors(vec![Color::WHITE], Size { width: 1, height: 1 });
+        // Left half of the quad has its normal vectors tilted away from the light, the right one is flat
+        let tilted = Color { r: 255, g: 0, b: 128 };
+        let flat = Color { r: 0, g: 0, b: 255 };
+        let normal_map = Image::from_colors(vec![tilted, flat, flat], Size { width: 3, height: 1 });
+
+        let render = |material: &Material| {
+            let mut renderer = headless(80, 40);
+            renderer.light(&vec3(0.0, 0.0, 1.0));
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(&mesh, material, &Vec3::ZERO);
+            renderer.drawer.to_image()
+        };
+
+        let plain = render(&Material::new(diffuse.clone()));
+        let mapped = render(&Material::new(diffuse).with_normal_map(normal_map));
+
+        assert!(pixel(&mapped, 30, 20).r < pixel(&mapped, 50, 20).r);
+        assert_eq!(pixel(&mapped, 50, 20).r, pixel(&plain, 50, 20).r);
+    }
+
+    #[test]
+    fn reinhard_keeps_bright_pixels_below_white() {
+        let mut renderer = headless(4, 2);
+        let gray = Color { r: 200, g: 200, b: 200 };
+        assert_eq!(renderer.apply_light(&gray, 2.0).r, 255);
+
+        renderer.set_tonemap(ToneMap::Reinhard);
+        let mapped = renderer.apply_light(&gray, 2.0);
+        assert!(mapped.r < 255);
+        assert!(mapped.r > renderer.apply_light(&gray, 1.0).r);
+    }
+
+    #[test]
+    fn cached_vertices_give_same_frame_as_separate_triangles() {
+        let mesh = Mesh::from_obj_str(CUBE_OBJ).unwrap();
+        let texture = Image::from_colors(vec![Color::rgb(180, 120, 60)], Size { width: 1, height: 1 });
+        let material = Material::new(texture);
+        let position = vec3(0.2, -0.1, 0.0);
+
+        let render = |cached: bool| {
+            let mut renderer = headless(80, 40);
+            renderer.set_camera(&Camera::orbit(Vec3::ZERO, 4.0, 0.7, 0.4));
+            renderer.refresh_with(&Color::BLACK);
+
+            if cached {
+                renderer.model(&mesh, &material, &position);
+            }
+            else {
+                let shader = PhongShader::new(&material);
+                for triangle in mesh.triangles() {
+                    let vertex = |i: usize| Vertex {
+                        position: triangle.vertices[i] + position,
+                        uv: triangle.texture_coords[i],
+                        normal: triangle.normals[i]
+                    };
+                    renderer.triangle(&[vertex(0), vertex(1), vertex(2)], &shader);
+                }
+            }
+
+            renderer.drawer.to_image()
+        };
+
+        let (cached, uncached) = (render(true), render(false));
+        assert!(pixel(&cached, 40, 20).r > 0);
+        for y in 0..40 {
+            for x in 0..80 {
+                let (a, b) = (pixel(&cached, x, y), pixel(&uncached, x, y));
+                assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b));
+            }
+        }
+    }
+
+    #[test]
+    fn refresh_clears_to_clear_color() {
+        let mut renderer = headless(20, 10);
+        let teal = Color::rgb(0, 128, 128);
+        renderer.set_clear_color(teal);
+        renderer.refresh_with(&Color::WHITE);
+        renderer.refresh();
+
+        let frame = renderer.drawer.to_image();
+        for y in 0..frame.size().height {
+            for x in 0..frame.size().width {
+                let color = pixel(&frame, x, y);
+                assert_eq!((color.r, color.g, color.b), (0, 128, 128));
+            }
+        }
+    }
+
+    #[test]
+    fn full_screen_triangle_covers_last_row_and_column() {
+        let mut renderer = headless(40, 20);
+        let size = renderer.drawer.plane_size();
+        let bbox = renderer.bounding_box(
+            &vec3(-10.0, -10.0, 0.0), &vec3(30.0, -10.0, 0.0), &vec3(-10.0, 30.0, 0.0)
+        );
+        assert_eq!((bbox.min_x, bbox.min_y), (0, 0));
+        assert_eq!((bbox.max_x, bbox.max_y), (size.width - 1, size.height - 1));
+
+        renderer.refresh_with(&Color::BLACK);
+        let camera = Camera::new(vec3(0.0, 0.0, 1.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0));
+        renderer.set_camera(&camera);
+        draw_triangle(
+            &mut renderer,
+            [vec3(-50.0, -50.0, -2.0), vec3(50.0, -50.0, -2.0), vec3(0.0, 50.0, -2.0)],
+            &SolidShader(Color::WHITE)
+        );
+        assert_eq!(drawn_pixels(&renderer), (size.width * size.height) as usize);
+    }
+
+    #[test]
+    fn resize_reallocates_buffers_and_updates_aspect() {
+        // Wide and then tall window
+        let mut renderer = headless(80, 10);
+        let before = renderer.aspect_scale();
+
+        renderer.resize(WinSize { cols: 10, rows: 20 });
+        let size = renderer.drawer.plane_size();
+        assert_eq!(renderer.zbuffer().len(), (size.width * size.height) as usize);
+
+        let after = renderer.aspect_scale();
+        assert!(before.x < 1.0 && before.y == 1.0);
+        assert!(after.x == 1.0 && after.y < 1.0);
+    }
+
+    #[test]
+    fn marker_at_origin_is_in_screen_center() {
+        let mut renderer = headless(40, 20);
+        renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+        renderer.refresh_with(&Color::BLACK);
+        renderer.draw_marker(&Vec3::ZERO, &Color::RED, 3);
+
+        let frame = renderer.drawer.to_image();
+        let size = renderer.drawer.plane_size();
+        let red: Vec<(i32, i32)> = (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixel(&frame, x, y).r == 255)
+            .collect();
+
+        assert_eq!(red.len(), 9);
+        for (x, y) in red {
+            assert!((x - size.width / 2).abs() <= 2);
+            assert!((y - size.height / 2).abs() <= 2);
+        }
+    }
+
+    #[test]
+    fn two_toon_bands_give_two_light_levels() {
+        let cube = Mesh::from_obj_str(CUBE_OBJ).unwrap();
+        let mut renderer = headless(80, 40);
+        renderer.set_camera(&Camera::orbit(Vec3::ZERO, 5.0, 0.5, 0.4));
+        renderer.light(&vec3(0.3, 0.5, 1.0).normalized());
+        renderer.set_toon_bands(Some(2));
+        renderer.refresh_with(&Color::BLACK);
+
+        let probe = FrontFaceLight(std::cell::RefCell::new(Vec::new()));
+        renderer.model_with_shader(&cube, &probe, &Vec3::ZERO);
+
+        let mut levels = probe.0.into_inner();
+        assert!(levels.len() > 10);
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        levels.dedup();
+        assert_eq!(levels, vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn transparent_texels_discard_fragments() {
+        // Uncompressed 32-bit TGA of 3x1 BGRA pixels, only the first one fully transparent
+        let mut tga = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 1, 0, 32, 8];
+        tga.extend_from_slice(&[255, 255, 255, 0, 255, 255, 255, 255, 255, 255, 255, 128]);
+        let texture = Image::from_tga_bytes(&tga).unwrap();
+        assert!(texture.has_alpha());
+        assert_eq!((texture.alpha_at(0, 0), texture.alpha_at(2, 0)), (0, 128));
+
+        let mesh = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+        let mut renderer = headless(80, 40);
+        renderer.refresh_with(&Color::BLACK);
+        renderer.model(&mesh, &Material::new(texture), &Vec3::ZERO);
+
+        let clear_value = renderer.depth_mode.clear_value();
+        let width = renderer.drawer.plane_size().width;
+        let depth = |x: i32, y: i32| renderer.zbuffer()[(y * width + x) as usize];
+        // The left half of the quad samples the transparent texel
+        assert_eq!(depth(30, 20), clear_value);
+        assert!(depth(50, 20) != clear_value);
+    }
+
+    // Keeps the interpolated position of the fragment nearest to the centroid of the triangle
+    struct CentroidPosition(std::cell::RefCell<Option<(f32, Vec3)>>);
+
+    impl Shader for CentroidPosition {
+        fn fragment(&self, bary: Vec3, varyings: &Varyings) -> Option<Color> {
+            let third = 1.0 / 3.0;
+            let distance = (bary.x - third).abs() + (bary.y - third).abs() + (bary.z - third).abs();
+            let mut nearest = self.0.borrow_mut();
+            if nearest.is_none_or(|(nearest_distance, _)| distance < nearest_distance) {
+                *nearest = Some((distance, varyings.position));
+            }
+            Some(Color::WHITE)
+        }
+    }
+
+    #[test]
+    fn position_at_centroid_is_average_of_vertices() {
+        let mut renderer = headless(160, 80);
+        renderer.set_camera(&Camera::new(vec3(0.5, 0.5, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+        renderer.refresh_with(&Color::BLACK);
+
+        // Tilted towards the camera, so that the vertices have different depths
+        let vertices = [vec3(-1.0, -0.5, -0.5), vec3(1.2, -0.8, 0.4), vec3(0.1, 1.0, 0.2)];
+        let shader = CentroidPosition(std::cell::RefCell::new(None));
+        draw_triangle(&mut renderer, vertices, &shader);
+
+        let (distance, position) = shader.0.into_inner().unwrap();
+        let average = (1.0 / 3.0) * (vertices[0] + vertices[1] + vertices[2]);
+        assert!(distance < 0.05);
+        assert!((position - average).len() < 0.05);
+    }
+
+    #[test]
+    fn draws_every_instance() {
+        let mesh = Mesh::from_obj_str("\
+            v -0.2 -0.2 0\nv 0.2 -0.2 0\nv 0 0.2 0\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\n").unwrap();
+        let texture = Image::from_colors(vec![Color::WHITE], Size { width: 1, height: 1 });
+        let mut renderer = headless(80, 40);
+        renderer.light(&vec3(0.0, 0.0, 1.0));
+        renderer.refresh_with(&Color::BLACK);
+        renderer.model_instanced(
+            &mesh, &Material::new(texture),
+            &[transform::translation(&vec3(-0.5, 0.0, 0.0)), transform::translation(&vec3(0.5, 0.0, 0.0))]
+        );
+
+        let frame = renderer.drawer.to_image();
+        let y = renderer.drawer.plane_size().height / 2;
+        let (center, left, right) = (pixel(&frame, 40, y), pixel(&frame, 20, y), pixel(&frame, 60, y));
+        assert_eq!(center.r, 0);
+        assert!(left.r > 0);
+        assert!(right.r > 0);
+    }
+
+    #[test]
+    fn gouraud_agrees_with_phong_except_for_highlights() {
+        let gray = Image::from_colors(vec![Color::rgb(60, 60, 60)], Size { width: 1, height: 1 });
+        let render = |mesh: &Mesh, shading_mode| {
+            let mut renderer = headless(80, 40);
+            renderer.light(&vec3(0.0, 0.0, 1.0));
+            renderer.set_shading_mode(shading_mode);
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(mesh, &Material::new(gray.clone()), &Vec3::ZERO);
+            pixel(&renderer.drawer.to_image(), 40, 40)
+        };
+
+        let flat_quad = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+        let phong = render(&flat_quad, ShadingMode::Smooth);
+        let gouraud = render(&flat_quad, ShadingMode::Gouraud);
+        assert!((i32::from(phong.r) - i32::from(gouraud.r)).abs() <= 1);
+
+        // Normal vectors spread outwards put the highlight in the middle, between the vertices
+        let curved_quad = Mesh::from_obj_str("\
+            v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\n\
+            vt 0 0\n\
+            vn -1 -1 1\nvn 1 -1 1\nvn 1 1 1\nvn -1 1 1\n\
+            f 1/1/1 2/1/2 3/1/3\nf 1/1/1 3/1/3 4/1/4\n").unwrap();
+        let phong = render(&curved_quad, ShadingMode::Smooth);
+        let gouraud = render(&curved_quad, ShadingMode::Gouraud);
+        assert!(i32::from(phong.r) - i32::from(gouraud.r) > 10);
+    }
+
+    #[test]
+    fn textured_triangle_without_normal_map_is_shaded() {
+        let mesh = Mesh::from_obj_str("\
+            v -1 -1 0\nv 1 -1 0\nv 0 1 0\n\
+            vt 0 0\nvt 1 0\nvt 0.5 1\nvn 0 0 1\n\
+            f 1/1/1 2/2/1 3/3/1\n").unwrap();
+        let (light, dark) = (Color::rgb(200, 100, 50), Color::rgb(100, 50, 25));
+        let texture = Image::from_colors(vec![light, light, dark, dark], Size { width: 2, height: 2 });
+        let material = Material::new(texture);
+        assert!(material.normal.is_none());
+
+        let render = |light: Vec3| {
+            let mut renderer = headless(80, 40);
+            renderer.light(&light.normalized());
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(&mesh, &material, &Vec3::ZERO);
+            assert!(renderer.stats().fragments > 0);
+            pixel(&renderer.drawer.to_image(), 40, 40)
+        };
+
+        // Lit head-on and from the side
+        let front = render(vec3(0.0, 0.0, 1.0));
+        let side = render(vec3(1.0, 0.0, 0.3));
+        assert!(side.r > 0);
+        assert!(front.r > side.r);
+    }
+
+    #[test]
+    fn degenerate_uv_falls_back_to_geometric_normal() {
+        // All vertices share the same UV coordinates, so the tangent basis cannot be found
+        let mesh = Mesh::from_obj_str("\
+            v -1 -1 0\nv 1 -1 0\nv 0 1 0\n\
+            vt 0.5 0.5\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\n").unwrap();
+        let diffuse = Image::from_colors(vec![Color::rgb(60, 60, 60)], Size { width: 1, height: 1 });
+        let tilted = Image::from_colors(vec![Color::rgb(255, 0, 128)], Size { width: 1, height: 1 });
+
+        let render = |material: &Material| {
+            let mut renderer = headless(80, 40);
+            renderer.light(&vec3(0.0, 0.0, 1.0));
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(&mesh, material, &Vec3::ZERO);
+            (renderer.stats().fragments, pixel(&renderer.drawer.to_image(), 40, 40))
+        };
+
+        let (plain_fragments, plain) = render(&Material::new(diffuse.clone()));
+        let (mapped_fragments, mapped) = render(&Material::new(diffuse).with_normal_map(tilted));
+        assert!(mapped_fragments > 0);
+        assert_eq!(mapped_fragments, plain_fragments);
+        assert_eq!((mapped.r, mapped.g, mapped.b), (plain.r, plain.g, plain.b));
+    }
+
+    #[test]
+    fn higher_shininess_shrinks_highlight() {
+        let mut renderer = headless(4, 2);
+        renderer.clear_lights();
+        renderer.add_light(Light::Point {
+            position: vec3(0.0, 0.0, 1.0),
+            constant: 1.0,
+            linear: 0.0,
+            quadratic: 0.0
+        });
+
+        // Points of the z = 0 plane under the light where the specular light is strong
+        let highlight_size = |shininess| {
+            let normal = vec3(0.0, 0.0, 1.0);
+            (-20..=20)
+                .flat_map(|x| (-20..=20).map(move |y| vec3(x as f32 / 10.0, y as f32 / 10.0, 0.0)))
+                .filter(|position| renderer.calc_direct_light(&normal, position, shininess).1 > 0.5)
+                .count()
+        };
+
+        let (wide, narrow) = (highlight_size(4.0), highlight_size(64.0));
+        assert!(narrow > 0);
+        assert!(wide > narrow);
+    }
+
+    // Counts shaded fragments of the triangle lying in the z = -0.5 plane
+    struct BackTriangleCounter(std::cell::Cell<usize>);
+
+    impl Shader for BackTriangleCounter {
+        fn fragment(&self, _bary: Vec3, varyings: &Varyings) -> Option<Color> {
+            if varyings.triangle.world.iter().all(|vertex| vertex.z == -0.5) {
+                self.0.set(self.0.get() + 1);
+            }
+            Some(Color::WHITE)
+        }
+    }
+
+    #[test]
+    fn depth_prepass_skips_hidden_fragments() {
+        // The hidden triangle comes first, so it is shaded before the front one covers it
+        let mesh = Mesh::from_obj_str("\
+            v -1 -1 -0.5\nv 1 -1 -0.5\nv 0 1 -0.5\n\
+            v -1 -1 0.5\nv 1 -1 0.5\nv 0 1 0.5\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\nf 4/1/1 5/1/1 6/1/1\n").unwrap();
+
+        let hidden_fragments = |depth_prepass: bool| {
+            let mut renderer = headless(80, 40);
+            renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+            renderer.set_depth_prepass(depth_prepass);
+            renderer.refresh_with(&Color::BLACK);
+            if depth_prepass {
+                renderer.depth_prepass(&mesh, &Vec3::ZERO);
+            }
+
+            let counter = BackTriangleCounter(std::cell::Cell::new(0));
+            renderer.model_with_shader(&mesh, &counter, &Vec3::ZERO);
+            counter.0.get()
+        };
+
+        assert!(hidden_fragments(false) > 0);
+        assert_eq!(hidden_fragments(true), 0);
+    }
+
+    // Moves the vertices towards the camera and counts the shaded fragments
+    struct DisplacingCounter(std::cell::Cell<usize>);
+
+    impl Shader for DisplacingCounter {
+        fn vertex(&self, position: &Vec3) -> Vec3 {
+            *position + vec3(0.0, 0.0, 0.3)
+        }
+
+        fn fragment(&self, _bary: Vec3, _varyings: &Varyings) -> Option<Color> {
+            self.0.set(self.0.get() + 1);
+            Some(Color::WHITE)
+        }
+    }
+
+    #[test]
+    fn depth_prepass_uses_vertex_shader() {
+        let mesh = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+
+        let shaded_fragments = |depth_prepass: bool| {
+            let mut renderer = headless(40, 20);
+            renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+            renderer.set_depth_prepass(depth_prepass);
+            renderer.refresh_with(&Color::BLACK);
+
+            let shader = DisplacingCounter(std::cell::Cell::new(0));
+            if depth_prepass {
+                renderer.depth_prepass_with_shader(&mesh, &shader, &Vec3::ZERO);
+            }
+            renderer.model_with_shader(&mesh, &shader, &Vec3::ZERO);
+            shader.0.get()
+        };
+
+        let without_prepass = shaded_fragments(false);
+        assert!(without_prepass > 0);
+        assert_eq!(shaded_fragments(true), without_prepass);
+    }
+
+    #[test]
+    fn unproject_inverts_projection() {
+        let mut renderer = headless(80, 40);
+        renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+        renderer.refresh_with(&Color::BLACK);
+        draw_triangle(
+            &mut renderer,
+            [vec3(-5.0, -5.0, -1.0), vec3(5.0, -5.0, -1.0), vec3(0.0, 5.0, -1.0)],
+            &SolidShader(Color::WHITE)
+        );
+
+        let world = vec3(0.3, -0.2, -1.0);
+        let screen = renderer.transform(&world).unwrap();
+        let (x, y) = Renderer::to_buffer_coordinates(
+            Vec2 { x: screen.x, y: screen.y }, &renderer.drawer.plane_size(), &renderer.aspect_scale()
+        );
+        let stored_depth = renderer.zbuffer()[(y * renderer.drawer.plane_size().width + x) as usize];
+        assert!((stored_depth - screen.z).abs() < 1e-5);
+
+        // Off by less than the size of a pixel, as the coordinates are rounded to pixels
+        let unprojected = renderer.unproject(x, y, stored_depth).unwrap();
+        assert!((unprojected.z - world.z).abs() < 1e-3);
+        assert!((unprojected - world).len() < 0.1);
+    }
+
+    #[test]
+    fn zbuffer_keeps_nearer_depth_of_overlap() {
+        let mut renderer = headless(40, 20);
+        renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+        renderer.refresh_with(&Color::BLACK);
+
+        let near = [vec3(-1.0, -1.0, 0.5), vec3(1.0, -1.0, 0.5), vec3(0.0, 1.0, 0.5)];
+        let far = [vec3(-1.0, -1.0, -0.5), vec3(1.0, -1.0, -0.5), vec3(0.0, 1.0, -0.5)];
+        draw_triangle(&mut renderer, far, &SolidShader(Color::WHITE));
+        draw_triangle(&mut renderer, near, &SolidShader(Color::WHITE));
+
+        let size = renderer.drawer.plane_size();
+        let center = (size.height / 2 * size.width + size.width / 2) as usize;
+        let near_depth = renderer.transform(&near[0]).unwrap().z;
+        assert!((renderer.zbuffer()[center] - near_depth).abs() < 1e-5);
+        assert!(renderer.zbuffer()[center] > renderer.transform(&far[0]).unwrap().z);
+    }
+
+    #[test]
+    fn ao_radius_ignores_distant_occluders() {
+        let mut renderer = headless(80, 40);
+        renderer.set_ao_quality(AoQuality::Full);
+        let size = renderer.drawer.plane_size();
+
+        // Flat surface facing the camera with a much nearer block on its right edge
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let depth = if x >= size.width - 10 { 1.0 } else { 0.0 };
+                renderer.zbuffer[(y * size.width + x) as usize] = renderer.depth_mode.encode(depth);
+            }
+        }
+
+        let (x, y) = (size.width - 40, size.height / 2);
+        let unbounded = renderer.ambient_occlusion(x, y);
+        renderer.set_ao_radius(Some(5));
+        let bounded = renderer.ambient_occlusion(x, y);
+
+        assert!(bounded > 0.99);
+        assert!(unbounded < bounded - 0.1);
+    }
+
+    #[test]
+    fn identical_frame_is_unchanged() {
+        let cube = Mesh::from_obj_str(CUBE_OBJ).unwrap();
+        let material = Material::new(Image::from_colors(vec![Color::WHITE], Size { width: 1, height: 1 }));
+        let mut renderer = headless(40, 20);
+        renderer.set_camera(&Camera::orbit(Vec3::ZERO, 5.0, 0.5, 0.4));
+        renderer.set_edge_outline(0.05, Color::RED);
+
+        let render = |renderer: &mut Renderer| {
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(&cube, &material, &Vec3::ZERO);
+        };
+
+        render(&mut renderer);
+        assert!(renderer.frame_changed());
+        renderer.display().unwrap();
+
+        render(&mut renderer);
+        assert!(!renderer.frame_changed());
+        renderer.display().unwrap();
+
+        // Layers drawn over the scene are a part of the frame too
+        render(&mut renderer);
+        renderer.drawer.set_layer(Layer::Overlay);
+        renderer.drawer.point(0, 0, &Color::GREEN);
+        assert!(renderer.frame_changed());
+    }
+
+    #[test]
+    fn outlines_only_boundary_of_near_geometry() {
+        let mut renderer = headless(40, 20);
+        renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+        renderer.refresh_with(&Color::BLACK);
+
+        let background = [vec3(-9.0, -9.0, -1.0), vec3(9.0, -9.0, -1.0), vec3(0.0, 9.0, -1.0)];
+        let near = [vec3(-0.5, -0.5, 0.5), vec3(0.5, -0.5, 0.5), vec3(0.0, 0.5, 0.5)];
+        draw_triangle(&mut renderer, background, &SolidShader(Color::WHITE));
+        draw_triangle(&mut renderer, near, &SolidShader(Color::WHITE));
+
+        let size = renderer.drawer.plane_size();
+        let far_depth = renderer.transform(&background[0]).unwrap().z;
+        let is_near = |x: i32, y: i32| {
+            renderer.zbuffer()[(y * size.width + x) as usize] > far_depth + 1e-3
+        };
+        let edges = renderer.depth_edges(0.01);
+
+        let mut outlined = 0;
+        for y in 1..size.height - 1 {
+            for x in 1..size.width - 1 {
+                let next_to_far = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter()
+                    .any(|(dx, dy)| !is_near(x + dx, y + dy));
+                let edge = edges[(y * size.width + x) as usize];
+                assert_eq!(edge, is_near(x, y) && next_to_far);
+                outlined += edge as usize;
+            }
+        }
+        assert!(outlined > 0);
+        assert!(!edges[(size.height / 2 * size.width + size.width / 2) as usize]);
+    }
+
+    #[test]
+    fn nearer_fragments_have_larger_depth_values() {
+        for depth_mode in [DepthMode::Standard, DepthMode::Reversed] {
+            let mut renderer = headless(40, 20);
+            renderer.set_depth_mode(depth_mode);
+            renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+            renderer.refresh_with(&Color::BLACK);
+
+            // Left edge towards the camera, right edge away from it
+            draw_triangle(
+                &mut renderer,
+                [vec3(-1.0, -1.0, 1.0), vec3(1.0, -1.0, -1.0), vec3(-1.0, 1.0, 1.0)],
+                &SolidShader(Color::WHITE)
+            );
+
+            let size = renderer.drawer.plane_size();
+            let depth = renderer.depth_image();
+            let row = &depth[(size.height / 2 * size.width) as usize..((size.height / 2 + 1) * size.width) as usize];
+            let covered: Vec<u16> = row.iter().copied().filter(|&depth| depth != 0).collect();
+
+            assert!(covered.len() > 5);
+            assert!(covered.windows(2).all(|pair| pair[0] >= pair[1]));
+            assert!(covered[0] > covered[covered.len() - 1]);
+            assert_eq!(row[0], 0);
+        }
+    }
+
+    #[test]
+    fn fixed_point_coverage_is_within_a_pixel_of_float() {
+        let triangles = [
+            [vec3(-0.9, -0.8, 0.0), vec3(0.7, -0.6, 0.0), vec3(-0.2, 0.9, 0.0)],
+            [vec3(0.1, 0.1, 0.2), vec3(0.95, 0.3, 0.2), vec3(0.4, 0.85, 0.2)],
+            [vec3(-0.6, -0.1, 0.4), vec3(-0.55, -0.05, 0.4), vec3(0.8, -0.9, 0.4)]
+        ];
+
+        let coverage = |rasterizer| {
+            let mut renderer = headless(40, 20);
+            renderer.set_rasterizer(rasterizer);
+            renderer.refresh_with(&Color::BLACK);
+            for triangle in triangles {
+                draw_triangle(&mut renderer, triangle, &SolidShader(Color::WHITE));
+            }
+
+            let clear_value = renderer.depth_mode.clear_value();
+            let covered: Vec<bool> = renderer.zbuffer().iter().map(|&depth| depth != clear_value).collect();
+            (covered, renderer.drawer.plane_size())
+        };
+
+        let (fixed, size) = coverage(Rasterizer::FixedPoint);
+        let (float, _) = coverage(Rasterizer::Float);
+        assert!(fixed.iter().filter(|&&covered| covered).count() > 100);
+
+        // Every pixel covered by one rasterizer has a covered pixel of the other one next to it
+        let near_covered = |coverage: &[bool], x: i32, y: i32| {
+            (-1..=1).any(|dy| (-1..=1).any(|dx| {
+                let (x, y) = (x + dx, y + dy);
+                x >= 0 && y >= 0 && x < size.width && y < size.height && coverage[(y * size.width + x) as usize]
+            }))
+        };
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let i = (y * size.width + x) as usize;
+                assert!(!fixed[i] || near_covered(&float, x, y));
+                assert!(!float[i] || near_covered(&fixed, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn static_shadows_are_drawn_again_only_after_changes() {
+        let cube = Mesh::from_obj_str(CUBE_OBJ).unwrap();
+        let light = vec3(1.0, 2.0, 3.0);
+        let mut renderer = headless(40, 20);
+        renderer.set_static_shadows(true);
+        renderer.light(&light);
+
+        let frame = |renderer: &mut Renderer, eye: Vec3, pos: Vec3, moved: bool| {
+            renderer.set_camera(&Camera::new(eye, Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+            renderer.refresh_with(&Color::BLACK);
+            if moved {
+                renderer.invalidate_shadows();
+            }
+            renderer.model_with_shader(&cube, &SolidShader(Color::WHITE), &pos);
+            renderer.shadow_buffer.iter().any(|depth| depth.is_finite())
+        };
+        let clear_shadow_buffer = |renderer: &mut Renderer| {
+            renderer.shadow_buffer.iter_mut().for_each(|depth| *depth = f32::NEG_INFINITY);
+        };
+
+        assert!(frame(&mut renderer, vec3(0.0, 0.0, 5.0), Vec3::ZERO, false));
+
+        // Only the camera moves and the same light is set again
+        clear_shadow_buffer(&mut renderer);
+        renderer.light(&light);
+        assert!(!frame(&mut renderer, vec3(3.0, 0.0, 4.0), Vec3::ZERO, false));
+
+        // Moved geometry is drawn into the shadow buffer in the same frame
+        let moved = vec3(0.5, 0.0, 0.0);
+        assert!(frame(&mut renderer, vec3(3.0, 0.0, 4.0), moved, true));
+
+        clear_shadow_buffer(&mut renderer);
+        assert!(!frame(&mut renderer, vec3(0.0, 0.0, 5.0), moved, false));
+
+        renderer.light(&vec3(-1.0, 2.0, 3.0));
+        assert!(frame(&mut renderer, vec3(0.0, 0.0, 5.0), moved, false));
+    }
+
+    // Terminal which takes a fixed time to receive every frame, remembering when each one started
+    struct SlowWriter {
+        flush_time: Duration,
+        flushes: std::rc::Rc<std::cell::RefCell<Vec<Instant>>>
+    }
+
+    impl std::io::Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes.borrow_mut().push(Instant::now());
+            std::thread::sleep(self.flush_time);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn adaptive_pacing_lowers_frame_rate_for_slow_terminal() {
+        let flush_time = Duration::from_millis(20);
+        let flushes = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let writer = SlowWriter { flush_time, flushes: flushes.clone() };
+        let mut renderer = Renderer::with_drawer(Drawer::with_writer(writer, WinSize { cols: 4, rows: 2 }));
+        renderer.set_adaptive_pacing(true);
+
+        for _ in 0..5 {
+            renderer.refresh_with(&Color::BLACK);
+            renderer.display().unwrap();
+        }
+
+        // Every frame is followed by as much idle time as it took to write out,
+        // instead of the next one being sent as soon as it is rendered
+        let flushes = flushes.borrow();
+        assert_eq!(flushes.len(), 5);
+        for pair in flushes.windows(2) {
+            assert!(pair[1] - pair[0] >= 2 * flush_time);
+        }
+        assert!(renderer.pacer.as_ref().unwrap().budget > FramePacer::MIN_BUDGET);
+    }
+
+    // Counts the transformed vertices
+    struct VertexCounter(std::cell::Cell<usize>);
+
+    impl Shader for VertexCounter {
+        fn vertex(&self, position: &Vec3) -> Vec3 {
+            self.0.set(self.0.get() + 1);
+            *position
+        }
+
+        fn fragment(&self, _bary: Vec3, _varyings: &Varyings) -> Option<Color> {
+            Some(Color::WHITE)
+        }
+    }
+
+    #[test]
+    fn model_range_transforms_only_vertices_of_its_faces() {
+        // Strip of triangles, each one sharing an edge with the previous one
+        let mut obj = String::new();
+        for i in 0..10 {
+            obj += &format!("v {} 0 0\nv {} 1 0\n", i, i);
+        }
+        obj += "vt 0 0\nvn 0 0 1\n";
+        for i in 0..9 {
+            let (bottom, top) = (2 * i + 1, 2 * i + 2);
+            obj += &format!("f {}/1/1 {}/1/1 {}/1/1\nf {}/1/1 {}/1/1 {}/1/1\n",
+                bottom, bottom + 2, top, top, bottom + 2, top + 2);
+        }
+        let mesh = Mesh::from_obj_str(&obj).unwrap();
+        let mut renderer = headless(20, 10);
+
+        let counter = VertexCounter(std::cell::Cell::new(0));
+        renderer.faces_with_shader(&mesh, &counter, &Vec3::ZERO, 2..4);
+        assert_eq!(counter.0.get(), 4);
+
+        let counter = VertexCounter(std::cell::Cell::new(0));
+        renderer.model_with_shader(&mesh, &counter, &Vec3::ZERO);
+        assert_eq!(counter.0.get(), mesh.vertex_count());
+    }
+
+    #[test]
+    fn model_range_draws_only_faces_in_range() {
+        // Triangle on the left, then one on the right
+        let mesh = Mesh::from_obj_str("\
+            v -0.9 -0.5 0\nv -0.1 -0.5 0\nv -0.5 0.5 0\n\
+            v 0.1 -0.5 0\nv 0.9 -0.5 0\nv 0.5 0.5 0\n\
+            vt 0 0\nvn 0 0 1\n\
+            f 1/1/1 2/1/1 3/1/1\nf 4/1/1 5/1/1 6/1/1\n").unwrap();
+        let material = Material::new(Image::from_colors(vec![Color::WHITE], Size { width: 1, height: 1 }));
+        let mut renderer = headless(40, 20);
+        renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+        renderer.refresh_with(&Color::BLACK);
+        renderer.model_range(&mesh, &material, &Vec3::ZERO, 0..1);
+
+        let size = renderer.drawer.plane_size();
+        let clear_value = renderer.depth_mode.clear_value();
+        let covered = |x: i32| renderer.zbuffer()[(size.height / 2 * size.width + x) as usize] != clear_value;
+        assert_eq!(renderer.stats().triangles, 1);
+        assert!(covered(size.width * 3 / 8));
+        assert!(!covered(size.width * 5 / 8));
+    }
+
+    #[test]
+    fn model_looks_the_same_under_matching_handedness() {
+        // A near red triangle on the right in front of a far blue one, with z negated for the left-handed import
+        let render = |handedness: Handedness, z: f32| {
+            let mut renderer = headless(40, 20);
+            renderer.set_handedness(handedness);
+            renderer.set_camera(&Camera::new(vec3(0.0, 0.0, 3.0 * z), Vec3::ZERO, vec3(0.0, 1.0, 0.0)));
+            renderer.refresh_with(&Color::BLACK);
+            let far = [vec3(-0.8, -0.8, -0.5 * z), vec3(0.8, -0.8, -0.5 * z), vec3(0.0, 0.8, -0.5 * z)];
+            let near = [vec3(0.1, -0.3, 0.5 * z), vec3(0.7, -0.3, 0.5 * z), vec3(0.4, 0.3, 0.5 * z)];
+            draw_triangle(&mut renderer, near, &SolidShader(Color::rgb(255, 0, 0)));
+            draw_triangle(&mut renderer, far, &SolidShader(Color::rgb(0, 0, 255)));
+            renderer.drawer.to_image()
+        };
+
+        let right = render(Handedness::Right, 1.0);
+        let left = render(Handedness::Left, -1.0);
+
+        let (width, height) = (right.size().width, right.size().height);
+        let red_columns: Vec<i32> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixel(&right, x, y).r == 255)
+            .map(|(x, _)| x)
+            .collect();
+        assert!(!red_columns.is_empty());
+        assert!(red_columns.iter().all(|&x| x > width / 2));
+
+        for y in 0..height {
+            for x in 0..width {
+                let (r, l) = (pixel(&right, x, y), pixel(&left, x, y));
+                assert_eq!((r.r, r.g, r.b), (l.r, l.g, l.b));
+            }
+        }
+    }
+
+    #[test]
+    fn flipped_v_samples_mirrored_texel() {
+        let mesh = Mesh::from_obj_str(QUAD_OBJ).unwrap();
+        // Red bottom row at v = 0, blue above it
+        let red = Color { r: 255, g: 0, b: 0 };
+        let blue = Color { r: 0, g: 0, b: 255 };
+        let material = Material::new(Image::from_colors(vec![red, blue, blue], Size { width: 1, height: 3 }));
+
+        let render = |flip_v: bool| {
+            let mut renderer = headless(80, 40);
+            renderer.set_flip_v(flip_v);
+            renderer.light(&vec3(0.0, 0.0, 1.0));
+            renderer.refresh_with(&Color::BLACK);
+            renderer.model(&mesh, &material, &Vec3::ZERO);
+            renderer.drawer.to_image()
+        };
+        let is_red = |color: Color| color.r > 0 && color.b == 0;
+        let is_blue = |color: Color| color.b > 0 && color.r == 0;
+
+        let plain = render(false);
+        assert!(is_blue(pixel(&plain, 40, 20)) && is_red(pixel(&plain, 40, 60)));
+
+        let flipped = render(true);
+        assert!(is_red(pixel(&flipped, 40, 20)) && is_blue(pixel(&flipped, 40, 60)));
+    }
+}
@@ -0,0 +1,291 @@
+use crate::camera::Camera;
+use crate::error::Error;
+use crate::image::Image;
+use crate::light::Light;
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::primitive::{
+    Color,
+    Size
+};
+use crate::renderer::Renderer;
+use crate::transform;
+use crate::vector::Vec3;
+
+// Model described by a scene, its files are read by Scene::load_models
+pub struct SceneModel {
+    pub mesh: String,
+    // MTL file, by default the one named by the mesh
+    pub material: Option<String>,
+    pub texture: Option<String>,
+    pub normal_map: Option<String>,
+    pub position: Vec3,
+    // Angles around the x, y and z axes, applied in this order
+    pub rotation: Vec3,
+    // Centers the mesh and scales it to fit from -1 to 1
+    pub normalize: bool
+}
+
+pub struct Model {
+    pub mesh: Mesh,
+    pub material: Material,
+    pub position: Vec3
+}
+
+// Scene description with one statement per line and '#' starting comments:
+//   background r g b
+//   camera eye_x eye_y eye_z target_x target_y target_z
+//   light x y z
+//   point_light x y z [constant linear quadratic]
+//   model path
+// Lines following a model line describe that model:
+//   material path
+//   texture path
+//   normal_map path
+//   position x y z
+//   rotation x y z
+//   normalize
+pub struct Scene {
+    pub background: Color,
+    pub camera: Option<Camera>,
+    pub lights: Vec<Light>,
+    pub models: Vec<SceneModel>
+}
+
+impl Scene {
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        let file = std::fs::File::open(path)?;
+        Self::from_reader(std::io::BufReader::new(file))
+    }
+
+    pub fn from_reader<R: std::io::BufRead>(reader: R) -> Result<Self, Error> {
+        let mut scene = Scene {
+            background: Color::BLACK,
+            camera: None,
+            lights: Vec::new(),
+            models: Vec::new()
+        };
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => &line[..]
+            };
+
+            let line: Vec<&str> = line.split_whitespace().collect();
+            if line.is_empty() {
+                continue;
+            }
+
+            scene.parse_statement(&line)?;
+        }
+
+        Ok(scene)
+    }
+
+    fn parse_statement(&mut self, line: &[&str]) -> Result<(), Error> {
+        match line[0] {
+            "background" => {
+                Self::expect_arguments(line, 3)?;
+                self.background = Color {
+                    r: line[1].parse::<u8>()?,
+                    g: line[2].parse::<u8>()?,
+                    b: line[3].parse::<u8>()?
+                };
+            },
+            "camera" => {
+                Self::expect_arguments(line, 6)?;
+                self.camera = Some(Camera::new(
+                    Self::parse_vec3(&line[1..4])?,
+                    Self::parse_vec3(&line[4..7])?,
+                    Vec3 { x: 0.0, y: 1.0, z: 0.0 }
+                ));
+            },
+            "light" => {
+                Self::expect_arguments(line, 3)?;
+                self.lights.push(Light::Directional {
+                    dir: Self::parse_vec3(&line[1..4])?.normalized()
+                });
+            },
+            "point_light" => {
+                let (constant, linear, quadratic) = match line.len() {
+                    4 => (1.0, 0.0, 0.0),
+                    7 => (line[4].parse::<f32>()?, line[5].parse::<f32>()?, line[6].parse::<f32>()?),
+                    _ => return Err(Error::Parse)
+                };
+
+                self.lights.push(Light::Point {
+                    position: Self::parse_vec3(&line[1..4])?,
+                    constant,
+                    linear,
+                    quadratic
+                });
+            },
+            "model" => {
+                Self::expect_arguments(line, 1)?;
+                self.models.push(SceneModel {
+                    mesh: line[1].to_string(),
+                    material: None,
+                    texture: None,
+                    normal_map: None,
+                    position: Vec3::ZERO,
+                    rotation: Vec3::ZERO,
+                    normalize: false
+                });
+            },
+            _ => self.parse_model_statement(line)?
+        }
+
+        Ok(())
+    }
+
+    fn parse_model_statement(&mut self, line: &[&str]) -> Result<(), Error> {
+        let model = match self.models.last_mut() {
+            Some(model) => model,
+            None => return Err(Error::Parse)
+        };
+
+        match line[0] {
+            "material" => {
+                Self::expect_arguments(line, 1)?;
+                model.material = Some(line[1].to_string());
+            },
+            "texture" => {
+                Self::expect_arguments(line, 1)?;
+                model.texture = Some(line[1].to_string());
+            },
+            "normal_map" => {
+                Self::expect_arguments(line, 1)?;
+                model.normal_map = Some(line[1].to_string());
+            },
+            "position" => {
+                Self::expect_arguments(line, 3)?;
+                model.position = Self::parse_vec3(&line[1..4])?;
+            },
+            "rotation" => {
+                Self::expect_arguments(line, 3)?;
+                model.rotation = Self::parse_vec3(&line[1..4])?;
+            },
+            "normalize" => {
+                Self::expect_arguments(line, 0)?;
+                model.normalize = true;
+            },
+            _ => return Err(Error::Parse)
+        }
+
+        Ok(())
+    }
+
+    fn expect_arguments(line: &[&str], count: usize) -> Result<(), Error> {
+        if line.len() != count + 1 {
+            return Err(Error::Parse);
+        }
+
+        Ok(())
+    }
+
+    fn parse_vec3(components: &[&str]) -> Result<Vec3, Error> {
+        Ok(Vec3 {
+            x: components[0].parse::<f32>()?,
+            y: components[1].parse::<f32>()?,
+            z: components[2].parse::<f32>()?
+        })
+    }
+
+    // Sets up the background, the lights and the camera,
+    // the renderer keeps its own lights and camera if the scene has none
+    pub fn configure(&self, renderer: &mut Renderer) {
+        renderer.set_clear_color(self.background);
+
+        if let Some(camera) = &self.camera {
+            renderer.set_camera(camera);
+        }
+
+        if !self.lights.is_empty() {
+            renderer.clear_lights();
+            for light in self.lights.iter() {
+                renderer.add_light(*light);
+            }
+        }
+    }
+
+    // Textures override the ones from the material, models without any are white
+    pub fn load_models(&self) -> Result<Vec<Model>, Error> {
+        let mut models = Vec::with_capacity(self.models.len());
+
+        for model in self.models.iter() {
+            let mut mesh = Mesh::from_file(&model.mesh)?;
+            if model.normalize {
+                mesh.center_and_normalize();
+            }
+
+            let unit_scale = Vec3 { x: 1.0, y: 1.0, z: 1.0 };
+            mesh.transform(&transform::model_matrix(&Vec3::ZERO, &model.rotation, &unit_scale));
+
+            let material_path = match (&model.material, mesh.material_library()) {
+                (Some(path), _) => Some(std::path::PathBuf::from(path)),
+                (None, Some(library)) => Some(
+                    std::path::Path::new(&model.mesh).with_file_name(library)
+                ),
+                (None, None) => None
+            };
+
+            let mut material = match material_path {
+                Some(path) => Material::from_mtl_file(path)?,
+                None => Material::new(Image::from_colors(vec![Color::WHITE], Size { width: 1, height: 1 }))
+            };
+
+            if let Some(path) = &model.texture {
+                material.diffuse = Image::from_file(path)?;
+            }
+
+            if let Some(path) = &model.normal_map {
+                material = material.with_normal_map(Image::from_file(path)?);
+            }
+
+            models.push(Model {
+                mesh,
+                material,
+                position: model.position
+            });
+        }
+
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawer::{
+        Drawer,
+        WinSize
+    };
+
+    #[test]
+    fn configures_renderer_from_scene() {
+        let scene = Scene::from_reader("\
+            # minimal scene\n\
+            background 10 20 30\n\
+            camera 0 1 3  0 0 0\n\
+            light 0 0 2\n".as_bytes()).unwrap();
+        let drawer = Drawer::with_writer(std::io::sink(), WinSize { cols: 20, rows: 10 });
+        let mut renderer = Renderer::with_drawer(drawer);
+        scene.configure(&mut renderer);
+
+        assert_eq!((scene.background.r, scene.background.g, scene.background.b), (10, 20, 30));
+
+        assert_eq!(renderer.lights().len(), 1);
+        match renderer.lights()[0] {
+            Light::Directional { dir } => assert!((dir - Vec3 { x: 0.0, y: 0.0, z: 1.0 }).len() < 1e-6),
+            Light::Point { .. } => panic!("expected a directional light")
+        }
+
+        let eye = Vec3 { x: 0.0, y: 1.0, z: 3.0 };
+        let expected = Camera::new(eye, Vec3::ZERO, Vec3 { x: 0.0, y: 1.0, z: 0.0 }).view_matrix();
+        assert!(renderer.view_matrix().approx_eq(&expected, 1e-6));
+        // The eye ends up in the origin of the camera space
+        assert!((*renderer.view_matrix() * eye.homo_point()).point_proj().len() < 1e-5);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::image::Image;
+use crate::material::Material;
+use crate::primitive::Color;
+use crate::renderer::{
+    Renderer,
+    ShadingMode
+};
+use crate::vector::{
+    Vec2,
+    Vec3
+};
+
+// Vertex attributes of the rasterized triangle
+pub struct TriangleAttributes {
+    // Vertices after projection
+    pub screen: [Vec3; 3],
+    // Vertices in world space
+    pub world: [Vec3; 3],
+    pub uv: [Vec2; 3],
+    // Normal vectors in view space
+    pub normals: [Vec3; 3],
+    // Vertices as seen from the shadow casting light, missing if any of them cannot be projected
+    pub shadow: Option<[Vec3; 3]>,
+    pub colors: Option<[Color; 3]>,
+    // Ambient occlusion baked into the vertices of the mesh
+    pub ambient_occlusion: Option<[f32; 3]>
+}
+
+pub struct Varyings<'a> {
+    pub triangle: &'a TriangleAttributes,
+    // Values interpolated at the fragment
+    pub position: Vec3,
+    pub uv: Vec2,
+    pub normal: Vec3,
+    // Interpolated vertex color, if the triangle has vertex colors
+    pub color: Option<Color>,
+    // Diffuse and specular light interpolated from the vertices in Gouraud shading mode
+    pub direct_light: Option<(f32, f32)>,
+    // Baked ambient occlusion interpolated from the vertices, if the mesh has it
+    pub ambient_occlusion: Option<f32>,
+    // Fragment coordinates on the drawing plane
+    pub x: i32,
+    pub y: i32,
+    pub renderer: &'a Renderer
+}
+
+pub trait Shader {
+    // Called for every vertex in world space before it is projected
+    fn vertex(&self, position: &Vec3) -> Vec3 {
+        *position
+    }
+
+    // Returns the color of the fragment or None if it should be discarded
+    fn fragment(&self, bary: Vec3, varyings: &Varyings) -> Option<Color>;
+
+    // Shininess of the specular light computed at the vertices in Gouraud shading mode
+    fn shininess(&self) -> f32 {
+        Material::DEFAULT_SHININESS
+    }
+}
+
+pub struct PhongShader<'a> {
+    material: &'a Material
+}
+
+impl<'a> PhongShader<'a> {
+    pub fn new(material: &'a Material) -> Self {
+        PhongShader { material }
+    }
+
+    fn sample(map: &Image, uv: &Vec2) -> Color {
+        let (x, y) = Renderer::calc_texture_coords(uv, map);
+        *map.at(x, y)
+    }
+}
+
+impl<'a> Shader for PhongShader<'a> {
+    fn fragment(&self, bary: Vec3, varyings: &Varyings) -> Option<Color> {
+        let triangle = varyings.triangle;
+        let renderer = varyings.renderer;
+        let material = self.material;
+
+        let texture_coordinates = Renderer::calc_texture_coords(&varyings.uv, &material.diffuse);
+
+        // Fully transparent texels cut holes in the surface
+        if material.diffuse.alpha_at(texture_coordinates.0, texture_coordinates.1) == 0 {
+            return None;
+        }
+
+        let normal_vector = match (renderer.shading_mode(), &material.normal) {
+            (ShadingMode::Smooth, Some(normal_map)) => Renderer::calc_normal_vector(
+                &varyings.normal, &triangle.screen, &triangle.uv, &varyings.uv, normal_map
+            ),
+            _ => varyings.normal
+        };
+
+        let shadow_light = match &triangle.shadow {
+            Some([s1, s2, s3]) => renderer.calc_shadow_light(&bary, s1, s2, s3),
+            None => 0.0
+        };
+        let specular_factor = match &material.specular {
+            Some(specular_map) => {
+                let specular = Self::sample(specular_map, &varyings.uv);
+                (f32::from(specular.r) + f32::from(specular.g) + f32::from(specular.b)) / (3.0 * 255.0)
+            },
+            None => 1.0
+        };
+
+        let ambient_light = renderer.ambient_light(varyings);
+        let light_intensity = match varyings.direct_light {
+            Some(direct_light) => renderer.combine_light(
+                direct_light, shadow_light, specular_factor, ambient_light
+            ),
+            None => renderer.calc_light_intensity(
+                &normal_vector, &varyings.position, shadow_light,
+                specular_factor, material.shininess,
+                ambient_light
+            )
+        };
+
+        let color = match varyings.color {
+            Some(color) => color,
+            None => *material.diffuse.at(texture_coordinates.0, texture_coordinates.1)
+        } * material.base_color;
+
+        let emission = match &material.emissive {
+            Some(emissive_map) => Self::sample(emissive_map, &varyings.uv),
+            None => Color::BLACK
+        };
+
+        // Lighting and emission are the only terms added together, so the sum is clamped only here
+        Some(renderer.apply_light(&color, light_intensity).saturating_add(&emission))
+    }
+
+    fn shininess(&self) -> f32 {
+        self.material.shininess
+    }
+}
@@ -0,0 +1,59 @@
+use crate::drawer::ColorMode;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering
+};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Makes Ctrl-C set a flag instead of killing the process, so that the terminal can be restored
+pub fn install_interrupt_handler() {
+    let handler = handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Terminals announce 24-bit colors through $COLORTERM or, less often, through $TERM
+pub fn detect_color_support() -> ColorMode {
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+
+    color_mode_from(&colorterm, &term)
+}
+
+// Color mode for the given values of $COLORTERM and $TERM, empty if they are not set
+pub fn color_mode_from(colorterm: &str, term: &str) -> ColorMode {
+    let supports_truecolor = |value: &str| value.contains("truecolor") || value.contains("24bit");
+
+    if supports_truecolor(colorterm) || supports_truecolor(term) || term.ends_with("-direct") {
+        ColorMode::TrueColor
+    }
+    else {
+        ColorMode::Ansi256
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_truecolor_from_environment() {
+        assert!(matches!(color_mode_from("truecolor", "xterm-256color"), ColorMode::TrueColor));
+        assert!(matches!(color_mode_from("24bit", ""), ColorMode::TrueColor));
+        assert!(matches!(color_mode_from("", "xterm-256color"), ColorMode::Ansi256));
+        assert!(matches!(color_mode_from("", "xterm-direct"), ColorMode::TrueColor));
+        assert!(matches!(color_mode_from("", ""), ColorMode::Ansi256));
+    }
+}
@@ -64,25 +64,146 @@ pub fn normal_perspective(c: f32) -> Matrix4 {
 }
 
 pub fn look_at(eye: &Vec3, center: &Vec3, up: &Vec3) -> Matrix4 {
+    // Camera basis, the camera looks in the -k direction
     let k = (*eye - *center).normalized();
     let i = cross(up, &k).normalized();
     let j = cross(&k, &i).normalized();
 
     // i j k are orthonormal so its inverse is equal to its transpose
     Matrix4::new([
-        [1.0, 0.0, 0.0, 0.0],
-        [0.0, 1.0, 0.0, 0.0],
-        [0.0, 0.0, 1.0, -(*eye - *center).len()],
-        [0.0, 0.0, 0.0, 1.0]
-    ]) * Matrix4::new([
         [i.x, i.y, i.z, 0.0],
         [j.x, j.y, j.z, 0.0],
         [k.x, k.y, k.z, 0.0],
         [0.0, 0.0, 0.0, 1.0]
-    ]) * Matrix4::new([
-        [1.0, 0.0, 0.0, -center.x],
-        [0.0, 1.0, 0.0, -center.y],
-        [0.0, 0.0, 1.0, -center.z],
+    ]) * translation(&(-1.0 * *eye))
+}
+
+pub fn translation(offset: &Vec3) -> Matrix4 {
+    Matrix4::new([
+        [1.0, 0.0, 0.0, offset.x],
+        [0.0, 1.0, 0.0, offset.y],
+        [0.0, 0.0, 1.0, offset.z],
+        [0.0, 0.0, 0.0, 1.0]
+    ])
+}
+
+pub fn scale(factors: &Vec3) -> Matrix4 {
+    Matrix4::new([
+        [factors.x, 0.0, 0.0, 0.0],
+        [0.0, factors.y, 0.0, 0.0],
+        [0.0, 0.0, factors.z, 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ])
+}
+
+// Rotations are counterclockwise when looking from the positive end of the axis
+pub fn rotate_x(angle: f32) -> Matrix4 {
+    let (sin, cos) = angle.sin_cos();
+    Matrix4::new([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, cos, -sin, 0.0],
+        [0.0, sin, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ])
+}
+
+pub fn rotate_y(angle: f32) -> Matrix4 {
+    let (sin, cos) = angle.sin_cos();
+    Matrix4::new([
+        [cos, 0.0, sin, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [-sin, 0.0, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ])
+}
+
+pub fn rotate_z(angle: f32) -> Matrix4 {
+    let (sin, cos) = angle.sin_cos();
+    Matrix4::new([
+        [cos, -sin, 0.0, 0.0],
+        [sin, cos, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
         [0.0, 0.0, 0.0, 1.0]
     ])
 }
+
+// Scales, then rotates by the angles around the x, y and z axes in this order and then translates
+pub fn model_matrix(translation_offset: &Vec3, rotation: &Vec3, scale_factors: &Vec3) -> Matrix4 {
+    translation(translation_offset) *
+        rotate_z(rotation.z) * rotate_y(rotation.y) * rotate_x(rotation.x) *
+        scale(scale_factors)
+}
+
+// Accumulates model transforms of nested objects
+pub struct TransformStack {
+    stack: Vec<Matrix4>
+}
+
+impl TransformStack {
+    pub fn new() -> Self {
+        TransformStack {
+            stack: vec![Matrix4::IDENTITY]
+        }
+    }
+
+    // Applies the transform before all the transforms already on the stack
+    pub fn push(&mut self, transform: &Matrix4) {
+        let current = self.current();
+        self.stack.push(current * *transform);
+    }
+
+    // The identity transform at the bottom of the stack is never removed
+    pub fn pop(&mut self) -> Option<Matrix4> {
+        if self.stack.len() > 1 {
+            self.stack.pop()
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn current(&self) -> Matrix4 {
+        self.stack[self.stack.len() - 1]
+    }
+}
+
+impl Default for TransformStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn look_at_moves_eye_to_origin() {
+        let eye = Vec3 { x: 1.0, y: 2.0, z: 3.0 };
+        let center = Vec3 { x: 4.0, y: 2.0, z: -1.0 };
+        let view = look_at(&eye, &center, &Vec3 { x: 0.0, y: 1.0, z: 0.0 });
+
+        let origin: Vec3 = (view * eye.homo_point()).into();
+        assert!(origin.approx_eq(&Vec3::ZERO, 1e-5));
+
+        let forward: Vec3 = (view * (center - eye).normalized().homo_vector()).into();
+        assert!(forward.approx_eq(&Vec3 { x: 0.0, y: 0.0, z: -1.0 }, 1e-5));
+    }
+
+    #[test]
+    fn stack_composes_translations() {
+        let mut stack = TransformStack::new();
+        stack.push(&translation(&Vec3 { x: 1.0, y: 2.0, z: 3.0 }));
+        stack.push(&translation(&Vec3 { x: -0.5, y: 0.5, z: 1.0 }));
+
+        let current = stack.current();
+        let offset = Vec3 { x: current[(0, 3)], y: current[(1, 3)], z: current[(2, 3)] };
+        assert!(offset.approx_eq(&Vec3 { x: 0.5, y: 2.5, z: 4.0 }, 1e-6));
+
+        stack.pop();
+        assert!(stack.current().approx_eq(&translation(&Vec3 { x: 1.0, y: 2.0, z: 3.0 }), 1e-6));
+        stack.pop();
+        assert!(stack.pop().is_none());
+        assert!(stack.current().approx_eq(&Matrix4::IDENTITY, 0.0));
+    }
+}
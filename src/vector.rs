@@ -10,7 +10,7 @@ macro_rules! impl_dot_product_next {
 
 macro_rules! declare_vector {
     ($type:ident, $($coord:ident),+) => {
-        #[derive(Clone, Copy)]
+        #[derive(Clone, Copy, PartialEq, Debug)]
         pub struct $type {
             $(pub $coord: f32),+
         }
@@ -28,6 +28,47 @@ macro_rules! declare_vector {
                     $($coord: self.$coord / len),+
                 }
             }
+
+            pub fn approx_eq(&self, other: &$type, epsilon: f32) -> bool {
+                true $(&& (self.$coord - other.$coord).abs() <= epsilon)+
+            }
+
+            // Hadamard product, the `*` operator is the dot product
+            pub fn component_mul(&self, other: &$type) -> $type {
+                $type {
+                    $($coord: self.$coord * other.$coord),+
+                }
+            }
+        }
+
+        impl std::ops::Index<usize> for $type {
+            type Output = f32;
+
+            fn index(&self, index: usize) -> &Self::Output {
+                let mut i = 0;
+                $(
+                    if index == i {
+                        return &self.$coord;
+                    }
+                    i += 1;
+                )+
+
+                panic!("Index {} out of range for a vector with {} coordinates", index, i)
+            }
+        }
+
+        impl std::ops::IndexMut<usize> for $type {
+            fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+                let mut i = 0;
+                $(
+                    if index == i {
+                        return &mut self.$coord;
+                    }
+                    i += 1;
+                )+
+
+                panic!("Index {} out of range for a vector with {} coordinates", index, i)
+            }
         }
 
         impl std::ops::Mul<f32> for $type {
@@ -85,24 +126,41 @@ declare_vector!(Vec3, x, y, z);
 
 impl Vec3 {
     pub fn homo_point(&self) -> Vec4 {
-        Vec4 {
-            x: self.x,
-            y: self.y,
-            z: self.z,
-            w: 1.0
-        }
+        Vec4::from((*self, 1.0))
     }
 
     pub fn homo_vector(&self) -> Vec4 {
-        Vec4 {
-            x: self.x,
-            y: self.y,
-            z: self.z,
-            w: 0.0
+        Vec4::from((*self, 0.0))
+    }
+
+    // Angle in radians, from 0 to pi
+    pub fn angle_between(&self, other: &Vec3) -> f32 {
+        let cos = (*self * *other) / (self.len() * other.len());
+        cos.clamp(-1.0, 1.0).acos()
+    }
+
+    // Component of the vector parallel to the axis, which does not have to be normalized
+    pub fn project_onto(&self, axis: &Vec3) -> Vec3 {
+        (*self * *axis) / (*axis * *axis) * *axis
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(array: [f32; 3]) -> Self {
+        Vec3 {
+            x: array[0],
+            y: array[1],
+            z: array[2]
         }
     }
 }
 
+impl From<Vec3> for [f32; 3] {
+    fn from(vec: Vec3) -> Self {
+        [vec.x, vec.y, vec.z]
+    }
+}
+
 pub fn cross(v: &Vec3, u: &Vec3) -> Vec3 {
     Vec3 {
         x: v.y * u.z - v.z * u.y,
@@ -123,12 +181,142 @@ impl Vec4 {
         }
     }
 
+    // Points with w close to 0 lie on the plane of the camera and have no projection
+    pub fn try_point_proj(&self) -> Option<Vec3> {
+        if self.w.abs() < f32::EPSILON {
+            return None;
+        }
+
+        Some(self.point_proj())
+    }
+
     pub fn vector_proj(&self) -> Vec3 {
+        self.xyz()
+    }
+
+    pub fn dot(&self, other: &Vec4) -> f32 {
+        *self * *other
+    }
+
+    // Drops the w coordinate
+    pub fn xyz(&self) -> Vec3 {
         Vec3 {
             x: self.x,
             y: self.y,
             z: self.z
         }
     }
+
+    // Drops the z and w coordinates
+    pub fn xy(&self) -> Vec2 {
+        Vec2 {
+            x: self.x,
+            y: self.y
+        }
+    }
+}
+
+impl From<(Vec3, f32)> for Vec4 {
+    fn from((vec, w): (Vec3, f32)) -> Self {
+        Vec4 {
+            x: vec.x,
+            y: vec.y,
+            z: vec.z,
+            w
+        }
+    }
 }
 
+impl From<Vec4> for Vec3 {
+    fn from(vec: Vec4) -> Self {
+        vec.xyz()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_at_infinity_have_no_projection() {
+        assert!(Vec4 { x: 1.0, y: 2.0, z: 3.0, w: 0.0 }.try_point_proj().is_none());
+        assert_eq!(
+            Vec4 { x: 1.0, y: 2.0, z: 3.0, w: 2.0 }.try_point_proj(),
+            Some(Vec3 { x: 0.5, y: 1.0, z: 1.5 })
+        );
+    }
+
+    #[test]
+    fn perpendicular_vectors_are_right_angle_apart() {
+        let x = Vec3 { x: 2.0, y: 0.0, z: 0.0 };
+        let y = Vec3 { x: 0.0, y: 0.5, z: 0.0 };
+
+        assert!((x.angle_between(&y) - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!(x.angle_between(&x).abs() < 1e-3);
+        assert!((x.angle_between(&(-1.0 * x)) - std::f32::consts::PI).abs() < 1e-3);
+    }
+
+    #[test]
+    fn projects_onto_axis() {
+        let v = Vec3 { x: 3.0, y: 4.0, z: 5.0 };
+
+        assert_eq!(v.project_onto(&Vec3 { x: 0.0, y: 2.0, z: 0.0 }), Vec3 { x: 0.0, y: 4.0, z: 0.0 });
+        let diagonal = v.project_onto(&Vec3 { x: 1.0, y: 1.0, z: 0.0 });
+        assert!((diagonal - Vec3 { x: 3.5, y: 3.5, z: 0.0 }).len() < 1e-6);
+    }
+
+    #[test]
+    fn component_mul_multiplies_coordinates() {
+        let product = Vec3 { x: 2.0, y: 3.0, z: 4.0 }.component_mul(&Vec3 { x: 1.0, y: 0.0, z: 2.0 });
+        assert_eq!(product, Vec3 { x: 2.0, y: 0.0, z: 8.0 });
+    }
+
+    #[test]
+    fn compares_vectors() {
+        let v = Vec3 { x: 1.0, y: 2.0, z: 3.0 };
+
+        assert_eq!(v, Vec3 { x: 1.0, y: 2.0, z: 3.0 });
+        assert_ne!(v, Vec3 { x: 1.0, y: 2.0, z: 3.001 });
+        assert!(v.approx_eq(&Vec3 { x: 1.0, y: 2.0, z: 3.001 }, 0.01));
+        assert!(!v.approx_eq(&Vec3 { x: 1.0, y: 2.1, z: 3.0 }, 0.01));
+    }
+
+    #[test]
+    fn converts_between_vectors_and_arrays() {
+        let v = Vec3 { x: 1.0, y: 2.0, z: 3.0 };
+
+        let array: [f32; 3] = v.into();
+        assert_eq!(array, [1.0, 2.0, 3.0]);
+        assert_eq!(Vec3::from(array), v);
+
+        let homo = Vec4::from((v, 4.0));
+        assert_eq!(homo, Vec4 { x: 1.0, y: 2.0, z: 3.0, w: 4.0 });
+        assert_eq!(Vec3::from(homo), v);
+    }
+
+    #[test]
+    fn indexes_coordinates() {
+        let mut v = Vec4 { x: 1.0, y: 2.0, z: 3.0, w: 4.0 };
+        assert_eq!([v[0], v[1], v[2], v[3]], [1.0, 2.0, 3.0, 4.0]);
+
+        for i in 0..4 {
+            v[i] = -(i as f32);
+        }
+        assert_eq!(v, Vec4 { x: 0.0, y: -1.0, z: -2.0, w: -3.0 });
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_index_out_of_range() {
+        let v = Vec2 { x: 1.0, y: 2.0 };
+        let _ = v[2];
+    }
+
+    #[test]
+    fn drops_trailing_components_and_takes_dot_product() {
+        let v = Vec4 { x: 1.0, y: 2.0, z: 3.0, w: 4.0 };
+        assert_eq!(v.xyz(), Vec3 { x: 1.0, y: 2.0, z: 3.0 });
+        assert_eq!(v.xy(), Vec2 { x: 1.0, y: 2.0 });
+        assert_eq!(v.dot(&Vec4 { x: -1.0, y: 0.5, z: 2.0, w: 0.25 }), 7.0);
+    }
+}